@@ -0,0 +1,368 @@
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+use tokio::sync::mpsc::channel;
+
+use crate::ipc::{
+    audio::{AudioBackend, AudioEndpoint, DataFlow, Role},
+    control,
+    init::{get_audio_dictionary, IPCHandlers},
+    preferences::DevicePreferences,
+    sender::{AudioDeviceInfo, AudioDeviceMap},
+    settings::Settings,
+};
+
+pub const USAGE: &str = "\
+Usage:
+  OutputSwitcher set-default <device>
+  OutputSwitcher volume <+N|-N> [device]
+  OutputSwitcher volume <up|down> [device]
+  OutputSwitcher mute <on|off|toggle> [device]
+  OutputSwitcher list [--json]
+
+<device> is a device id or name. volume and mute act on the default output without one.
+volume up and down move one step of the device's own volume control.";
+
+/// A subcommand given on the command line.
+#[derive(Debug, Clone)]
+pub enum CliCommand {
+    SetDefault {
+        device: String,
+    },
+    Volume {
+        device: Option<String>,
+        step: f32,
+    },
+    HardwareStep {
+        device: Option<String>,
+        up: bool,
+    },
+    Mute {
+        device: Option<String>,
+        muted: Option<bool>,
+    },
+    List {
+        json: bool,
+    },
+}
+
+/// Parses the arguments after the executable name. No arguments mean a normal start.
+pub fn parse<I, S>(args: I) -> Result<Option<CliCommand>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let args = args
+        .into_iter()
+        .map(|a| a.as_ref().to_string())
+        .collect::<Vec<_>>();
+
+    let Some((command, rest)) = args.split_first() else {
+        return Ok(None);
+    };
+
+    let command = match (command.as_str(), rest) {
+        ("set-default", [device]) => CliCommand::SetDefault {
+            device: device.clone(),
+        },
+        ("volume", [direction, device @ ..])
+            if device.len() <= 1 && matches!(direction.as_str(), "up" | "down") =>
+        {
+            CliCommand::HardwareStep {
+                device: device.first().cloned(),
+                up: direction == "up",
+            }
+        }
+        ("volume", [step, device @ ..]) if device.len() <= 1 => {
+            if !step.starts_with(['+', '-']) {
+                return Err(anyhow!("Volume step must start with + or -: {:?}", step));
+            }
+            let step = step
+                .parse::<f32>()
+                .map_err(|e| anyhow!("Invalid volume step {:?}: {}", step, e))?;
+            if !(-100.0..=100.0).contains(&step) {
+                return Err(anyhow!("Volume step must be within ±100: {}", step));
+            }
+
+            CliCommand::Volume {
+                device: device.first().cloned(),
+                step: step / 100.0,
+            }
+        }
+        ("mute", [state, device @ ..]) if device.len() <= 1 => {
+            let muted = match state.as_str() {
+                "on" => Some(true),
+                "off" => Some(false),
+                "toggle" => None,
+                _ => return Err(anyhow!("Invalid mute state: {:?}", state)),
+            };
+
+            CliCommand::Mute {
+                device: device.first().cloned(),
+                muted,
+            }
+        }
+        ("list", []) => CliCommand::List { json: false },
+        ("list", [flag]) if flag == "--json" => CliCommand::List { json: true },
+        _ => return Err(anyhow!("Invalid arguments: {:?}", args)),
+    };
+
+    Ok(Some(command))
+}
+
+impl CliCommand {
+    /// The message the running instance handles this command with.
+    ///
+    /// `None` for commands which print, as their output would go to the running instance.
+    pub fn into_query(self) -> Option<IPCHandlers> {
+        match self {
+            CliCommand::SetDefault { device } => Some(IPCHandlers::SwitchDevice { device }),
+            CliCommand::Volume { device, step } => Some(IPCHandlers::VolumeStep { device, step }),
+            CliCommand::HardwareStep { device, up } => {
+                Some(IPCHandlers::VolumeHardwareStep { device, up })
+            }
+            CliCommand::Mute { device, muted } => Some(IPCHandlers::MuteChange { device, muted }),
+            CliCommand::List { .. } => None,
+        }
+    }
+}
+
+/// Runs the command on a backend of its own, for when no instance is running.
+pub fn run<B: AudioBackend>(command: CliCommand, settings: &Settings) -> Result<()> {
+    // notifications are not needed, the receiver is only kept so sending does not fail
+    let (tx, _rx) = channel(256);
+    let is = Arc::new(B::new(&tx)?);
+    let audio_dict = get_audio_dictionary(&is)?;
+
+    match command {
+        CliCommand::SetDefault { device } => {
+            control::switch_device(control::find_device(&audio_dict, &device)?)?;
+        }
+        CliCommand::Volume { device, step } => {
+            let audio = control::target_device(&*is, &audio_dict, device.as_deref())?;
+            let max_volume = DevicePreferences::new(settings)?.max_volume(audio.id());
+            let volume = control::step_volume(audio, step, max_volume)?;
+            println!("{}: {:.0}", audio.name(), volume * 100.0);
+        }
+        CliCommand::HardwareStep { device, up } => {
+            let audio = control::target_device(&*is, &audio_dict, device.as_deref())?;
+            let max_volume = DevicePreferences::new(settings)?.max_volume(audio.id());
+            let volume = control::hardware_step(audio, up, max_volume)?;
+            println!("{}: {:.0}", audio.name(), volume * 100.0);
+        }
+        CliCommand::Mute { device, muted } => {
+            let audio = control::target_device(&*is, &audio_dict, device.as_deref())?;
+            let muted = control::change_mute(audio, muted)?;
+            println!(
+                "{}: {}",
+                audio.name(),
+                if muted { "muted" } else { "unmuted" }
+            );
+        }
+        CliCommand::List { json } => print_list(&*is, &audio_dict, json)?,
+    }
+
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct ListEntry {
+    #[serde(flatten)]
+    info: AudioDeviceInfo,
+    default: bool,
+}
+
+fn print_list<B: AudioBackend>(
+    is: &B,
+    audio_dict: &AudioDeviceMap<B::Endpoint>,
+    json: bool,
+) -> Result<()> {
+    let defaults = [DataFlow::Render, DataFlow::Capture]
+        .into_iter()
+        .filter_map(|flow| is.get_default_audio_id(flow, Role::Multimedia).ok())
+        .collect::<Vec<_>>();
+
+    if json {
+        let entries = audio_dict
+            .values()
+            .map(|audio| {
+                Ok(ListEntry {
+                    info: AudioDeviceInfo::from_audio(audio)?,
+                    default: defaults.iter().any(|id| id == audio.id()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+
+        return Ok(());
+    }
+
+    for flow in [DataFlow::Render, DataFlow::Capture] {
+        println!("{:?}:", flow);
+        for audio in audio_dict.values().filter(|a| a.flow() == flow) {
+            let mark = if defaults.iter().any(|id| id == audio.id()) {
+                "*"
+            } else {
+                " "
+            };
+            let muted = if audio.get_mute_state()? {
+                " (muted)"
+            } else {
+                ""
+            };
+            println!(
+                "{} {} [{:.0}]{}\n    {}",
+                mark,
+                audio.name(),
+                audio.get_volume()? * 100.0,
+                muted,
+                audio.id()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Lets `println!` reach the console the command was started from, since release builds use
+/// the windows subsystem and have no console of their own.
+#[cfg(windows)]
+pub fn attach_console() {
+    use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn attach_console() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Option<CliCommand>> {
+        parse(args.split_whitespace())
+    }
+
+    #[test]
+    fn starts_normally_without_arguments() {
+        assert!(parse_args("").unwrap().is_none());
+    }
+
+    #[test]
+    fn parses_volume_steps() {
+        let command = parse_args("volume +5").unwrap();
+        assert!(matches!(
+            command,
+            Some(CliCommand::Volume { device: None, step }) if step == 0.05
+        ));
+
+        let command = parse_args("volume -2.5 Speakers").unwrap();
+        assert!(matches!(
+            command,
+            Some(CliCommand::Volume { device: Some(device), step })
+                if device == "Speakers" && step == -0.025
+        ));
+
+        // an absolute volume is not supported, so a missing sign is not mistaken for one
+        assert!(parse_args("volume 5").is_err());
+        assert!(parse_args("volume +five").is_err());
+        assert!(parse_args("volume").is_err());
+        assert!(parse_args("volume +5 Speakers extra").is_err());
+    }
+
+    #[test]
+    fn parses_hardware_volume_steps() {
+        assert!(matches!(
+            parse_args("volume up").unwrap(),
+            Some(CliCommand::HardwareStep {
+                device: None,
+                up: true
+            })
+        ));
+        assert!(matches!(
+            parse_args("volume down Speakers").unwrap(),
+            Some(CliCommand::HardwareStep { device: Some(device), up: false })
+                if device == "Speakers"
+        ));
+        assert!(parse_args("volume up Speakers extra").is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_volume_steps() {
+        assert!(parse_args("volume +100").is_ok());
+        assert!(parse_args("volume -100").is_ok());
+        assert!(parse_args("volume +101").is_err());
+        assert!(parse_args("volume -1000").is_err());
+        assert!(parse_args("volume +inf").is_err());
+        assert!(parse_args("volume +NaN").is_err());
+    }
+
+    #[test]
+    fn parses_mute_states() {
+        let muted = |args: &str| match parse_args(args).unwrap() {
+            Some(CliCommand::Mute { muted, .. }) => muted,
+            command => panic!("{:?}", command),
+        };
+
+        assert_eq!(muted("mute on"), Some(true));
+        assert_eq!(muted("mute off"), Some(false));
+        assert_eq!(muted("mute toggle"), None);
+        assert!(matches!(
+            parse_args("mute on Headphones").unwrap(),
+            Some(CliCommand::Mute { device: Some(device), .. }) if device == "Headphones"
+        ));
+        assert!(parse_args("mute maybe").is_err());
+    }
+
+    #[test]
+    fn parses_list() {
+        assert!(matches!(
+            parse_args("list").unwrap(),
+            Some(CliCommand::List { json: false })
+        ));
+        assert!(matches!(
+            parse_args("list --json").unwrap(),
+            Some(CliCommand::List { json: true })
+        ));
+        assert!(parse_args("list --yaml").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_subcommands() {
+        assert!(parse_args("unmute").is_err());
+        assert!(parse_args("set-default").is_err());
+        assert!(parse_args("--help").is_err());
+    }
+
+    #[test]
+    fn turns_commands_into_queries() {
+        let query = |args: &str| parse_args(args).unwrap().unwrap().into_query();
+
+        assert!(matches!(
+            query("set-default Speakers"),
+            Some(IPCHandlers::SwitchDevice { device }) if device == "Speakers"
+        ));
+        assert!(matches!(
+            query("volume -10"),
+            Some(IPCHandlers::VolumeStep { device: None, step }) if step == -0.1
+        ));
+        assert!(matches!(
+            query("volume up"),
+            Some(IPCHandlers::VolumeHardwareStep {
+                device: None,
+                up: true
+            })
+        ));
+        assert!(matches!(
+            query("mute toggle"),
+            Some(IPCHandlers::MuteChange {
+                device: None,
+                muted: None
+            })
+        ));
+        // printed by the new process, not the running one
+        assert!(query("list --json").is_none());
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{anyhow, Result};
+use std::{collections::HashMap, str::FromStr, sync::Mutex};
+use tauri::{App, AppHandle, Manager, Wry};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+
+use crate::ipc::{
+    init::IPCHandlers,
+    send_query,
+    settings::{Settings, SettingsStore},
+};
+
+/// What a hotkey does. Every action goes through the backend as an `IPCHandlers` message.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum HotkeyAction {
+    /// Switches to the output after the default one.
+    CycleDevice,
+    /// `device` is an id or a device name.
+    SwitchDevice {
+        device: String,
+    },
+    /// Changes the default output's volume by `percent`, which may be negative.
+    VolumeStep {
+        percent: f32,
+    },
+    ToggleMute,
+    ApplyProfile {
+        name: String,
+    },
+}
+
+impl HotkeyAction {
+    fn to_query(&self) -> IPCHandlers {
+        match self {
+            HotkeyAction::CycleDevice => IPCHandlers::SwitchNext,
+            HotkeyAction::SwitchDevice { device } => IPCHandlers::SwitchDevice {
+                device: device.clone(),
+            },
+            HotkeyAction::VolumeStep { percent } => IPCHandlers::VolumeStep {
+                device: None,
+                step: percent / 100.0,
+            },
+            HotkeyAction::ToggleMute => IPCHandlers::MuteChange {
+                device: None,
+                muted: None,
+            },
+            HotkeyAction::ApplyProfile { name } => IPCHandlers::ProfileApply { name: name.clone() },
+        }
+    }
+}
+
+/// A chord such as `Ctrl+Alt+Right` and the action it triggers.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct HotkeyBinding {
+    pub chord: String,
+    pub action: HotkeyAction,
+}
+
+/// Parses every chord, failing on the first invalid one or on two chords for the same keys.
+pub fn parse_bindings(bindings: &[HotkeyBinding]) -> Result<HashMap<Shortcut, HotkeyAction>> {
+    let mut parsed = HashMap::new();
+    let mut chords: HashMap<Shortcut, &str> = HashMap::new();
+
+    for binding in bindings {
+        let shortcut = Shortcut::from_str(&binding.chord)
+            .map_err(|e| anyhow!("Invalid hotkey {:?}: {}", binding.chord, e))?;
+
+        if let Some(other) = chords.insert(shortcut, &binding.chord) {
+            return Err(anyhow!(
+                "Hotkeys {:?} and {:?} use the same keys",
+                other,
+                binding.chord
+            ));
+        }
+        parsed.insert(shortcut, binding.action.clone());
+    }
+
+    Ok(parsed)
+}
+
+/// The actions of the currently registered chords.
+#[derive(Default)]
+struct Hotkeys(Mutex<HashMap<Shortcut, HotkeyAction>>);
+
+/// Registers the configured hotkeys and registers them again whenever the settings change.
+pub fn setup(app: &mut App<Wry>, store: &SettingsStore) {
+    app.manage(Hotkeys::default());
+
+    let app_handle = app.handle().clone();
+    apply(&app_handle, &store.get());
+
+    let mut rx = store.subscribe();
+    tokio::spawn(async move {
+        while rx.changed().await.is_ok() {
+            let settings = rx.borrow_and_update().clone();
+            apply(&app_handle, &settings);
+        }
+    });
+}
+
+fn apply(app: &AppHandle, settings: &Settings) {
+    // settings are validated before they are stored, so this only fails on a broken invariant
+    let bindings = match parse_bindings(&settings.hotkeys) {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            log::error!("{:?}", e);
+            return;
+        }
+    };
+
+    let global_shortcut = app.global_shortcut();
+    if let Err(e) = global_shortcut.unregister_all() {
+        log::error!("@global_shortcut.unregister_all {:?}", e);
+    }
+
+    let mut registered = HashMap::new();
+    for (shortcut, action) in bindings {
+        // fails when another application already holds the chord
+        match global_shortcut.register(shortcut) {
+            Ok(()) => {
+                registered.insert(shortcut, action);
+            }
+            Err(e) => log::error!("Failed to register hotkey {:?}: {:?}", shortcut, e),
+        }
+    }
+
+    let hotkeys = app.state::<Hotkeys>();
+    match hotkeys.0.lock() {
+        Ok(mut hotkeys) => *hotkeys = registered,
+        Err(e) => log::error!("{:?}", e),
+    }
+}
+
+pub fn on_shortcut(app: &AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    if event.state() != ShortcutState::Pressed {
+        return;
+    }
+
+    let query = {
+        let hotkeys = app.state::<Hotkeys>();
+        let Ok(hotkeys) = hotkeys.0.lock() else {
+            return;
+        };
+        hotkeys.get(shortcut).map(HotkeyAction::to_query)
+    };
+
+    if let Some(query) = query {
+        send_query(app, query);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(chord: &str, action: HotkeyAction) -> HotkeyBinding {
+        HotkeyBinding {
+            chord: chord.to_string(),
+            action,
+        }
+    }
+
+    #[test]
+    fn parses_every_binding() {
+        let bindings = [
+            binding("Ctrl+Alt+Right", HotkeyAction::CycleDevice),
+            binding("Ctrl+Alt+Left", HotkeyAction::ToggleMute),
+        ];
+
+        let parsed = parse_bindings(&bindings).unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        let right = Shortcut::from_str("Ctrl+Alt+Right").unwrap();
+        assert_eq!(parsed.get(&right), Some(&HotkeyAction::CycleDevice));
+    }
+
+    #[test]
+    fn rejects_an_invalid_chord() {
+        let bindings = [
+            binding("Ctrl+Alt+Right", HotkeyAction::CycleDevice),
+            binding("Ctrl+Alt+NoSuchKey", HotkeyAction::ToggleMute),
+        ];
+
+        let e = parse_bindings(&bindings).unwrap_err();
+
+        assert!(e.to_string().contains("Ctrl+Alt+NoSuchKey"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_two_chords_for_the_same_keys() {
+        // the modifiers are the same in either order
+        let bindings = [
+            binding("Ctrl+Alt+Right", HotkeyAction::CycleDevice),
+            binding("Alt+Ctrl+Right", HotkeyAction::ToggleMute),
+        ];
+
+        let e = parse_bindings(&bindings).unwrap_err();
+
+        assert!(e.to_string().contains("use the same keys"), "{}", e);
+    }
+}
@@ -0,0 +1,393 @@
+use anyhow::{anyhow, Result};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc::Sender;
+
+use super::{
+    notifier::Notification, AudioBackend, AudioEndpoint, AudioSession, DataFlow, FormFactor, Role,
+};
+
+#[derive(Debug, Clone)]
+pub struct FakeDeviceState {
+    pub name: String,
+    pub flow: DataFlow,
+    pub form_factor: FormFactor,
+    pub volume: f32,
+    pub muted: bool,
+    pub sessions: BTreeMap<u32, AudioSession>,
+}
+
+impl FakeDeviceState {
+    pub fn new(name: &str, flow: DataFlow) -> Self {
+        Self {
+            name: name.to_string(),
+            flow,
+            form_factor: FormFactor::Unknown,
+            volume: 1.0,
+            muted: false,
+            sessions: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    devices: BTreeMap<String, FakeDeviceState>,
+    defaults: BTreeMap<(DataFlow, Role), String>,
+    app_devices: BTreeMap<u32, String>,
+}
+
+/// In-memory backend which behaves like a sound server without touching any platform API.
+///
+/// Every mutation emits the same `Notification` the real backends would, so the whole
+/// `prepare_backend` loop can be driven from scripts on any platform. Clones share the same
+/// devices, so a script can keep one while the backend loop owns another.
+#[derive(Clone)]
+pub struct FakeBackend {
+    state: Arc<Mutex<FakeState>>,
+    tx: Sender<Notification>,
+}
+
+impl FakeBackend {
+    pub fn with_devices(
+        tx: &Sender<Notification>,
+        devices: impl IntoIterator<Item = (String, FakeDeviceState)>,
+    ) -> Self {
+        let devices: BTreeMap<_, _> = devices.into_iter().collect();
+
+        // like a sound server, the first device of each direction starts as the default
+        let mut defaults = BTreeMap::new();
+        for (id, device) in devices.iter() {
+            for role in Role::ALL {
+                defaults
+                    .entry((device.flow, role))
+                    .or_insert_with(|| id.clone());
+            }
+        }
+
+        Self {
+            state: Arc::new(Mutex::new(FakeState {
+                devices,
+                defaults,
+                app_devices: BTreeMap::new(),
+            })),
+            tx: tx.clone(),
+        }
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, FakeState>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow!("FakeBackend state is poisoned"))
+    }
+
+    fn notify(&self, notification: Notification) {
+        if let Err(e) = self.tx.try_send(notification) {
+            log::warn!("FakeBackend failed to notify: {:?}", e);
+        }
+    }
+
+    pub fn add_device(&self, id: &str, device: FakeDeviceState) -> Result<()> {
+        {
+            let mut state = self.lock()?;
+            for role in Role::ALL {
+                state
+                    .defaults
+                    .entry((device.flow, role))
+                    .or_insert_with(|| id.to_string());
+            }
+            state.devices.insert(id.to_string(), device);
+        }
+
+        self.notify(Notification::DeviceAdded { id: id.to_string() });
+
+        Ok(())
+    }
+
+    pub fn remove_device(&self, id: &str) -> Result<()> {
+        let (flow, new_defaults) = {
+            let mut state = self.lock()?;
+            let flow = state
+                .devices
+                .remove(id)
+                .ok_or_else(|| anyhow!("No such device: {:?}", id))?
+                .flow;
+
+            let next = state
+                .devices
+                .iter()
+                .find(|(_, device)| device.flow == flow)
+                .map(|(id, _)| id.clone());
+
+            let mut new_defaults = Vec::new();
+            for role in Role::ALL {
+                if state.defaults.get(&(flow, role)).map(String::as_str) != Some(id) {
+                    continue;
+                }
+
+                match &next {
+                    Some(next) => {
+                        state.defaults.insert((flow, role), next.clone());
+                        new_defaults.push((role, next.clone()));
+                    }
+                    None => {
+                        state.defaults.remove(&(flow, role));
+                    }
+                }
+            }
+
+            (flow, new_defaults)
+        };
+
+        self.notify(Notification::DeviceRemoved { id: id.to_string() });
+        for (role, new_default) in new_defaults {
+            self.notify(Notification::DefaultDeviceChanged {
+                id: new_default,
+                flow,
+                role,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn app_device(&self, process_id: u32) -> Result<Option<String>> {
+        Ok(self.lock()?.app_devices.get(&process_id).cloned())
+    }
+
+    pub fn device(&self, id: &str) -> Result<FakeDeviceState> {
+        self.lock()?
+            .devices
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("No such device: {:?}", id))
+    }
+
+    fn update_device<F: FnOnce(&mut FakeDeviceState)>(&self, id: &str, f: F) -> Result<()> {
+        let (volume, muted) = {
+            let mut state = self.lock()?;
+            let device = state
+                .devices
+                .get_mut(id)
+                .ok_or_else(|| anyhow!("No such device: {:?}", id))?;
+            f(device);
+            (device.volume, device.muted)
+        };
+
+        self.notify(Notification::VolumeChanged {
+            id: id.to_string(),
+            volume,
+            muted,
+        });
+
+        Ok(())
+    }
+
+    fn update_session<F: FnOnce(&mut AudioSession)>(
+        &self,
+        id: &str,
+        process_id: u32,
+        f: F,
+    ) -> Result<()> {
+        let (volume, muted) = {
+            let mut state = self.lock()?;
+            let session = state
+                .devices
+                .get_mut(id)
+                .ok_or_else(|| anyhow!("No such device: {:?}", id))?
+                .sessions
+                .get_mut(&process_id)
+                .ok_or_else(|| anyhow!("No such session: {}", process_id))?;
+            f(session);
+            (session.volume, session.muted)
+        };
+
+        self.notify(Notification::SessionVolumeChanged {
+            id: id.to_string(),
+            pid: process_id,
+            volume,
+            muted,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_session(&self, id: &str, session: AudioSession) -> Result<()> {
+        let pid = session.pid;
+        self.lock()?
+            .devices
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("No such device: {:?}", id))?
+            .sessions
+            .insert(pid, session);
+
+        self.notify(Notification::SessionCreated {
+            id: id.to_string(),
+            pid,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_session(&self, id: &str, process_id: u32) -> Result<()> {
+        self.lock()?
+            .devices
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("No such device: {:?}", id))?
+            .sessions
+            .remove(&process_id)
+            .ok_or_else(|| anyhow!("No such session: {}", process_id))?;
+
+        self.notify(Notification::SessionDisconnected {
+            id: id.to_string(),
+            pid: process_id,
+            reason: 0,
+        });
+
+        Ok(())
+    }
+
+    fn set_default(&self, id: &str, roles: &[Role]) -> Result<()> {
+        let flow = {
+            let mut state = self.lock()?;
+            let flow = state
+                .devices
+                .get(id)
+                .ok_or_else(|| anyhow!("No such device: {:?}", id))?
+                .flow;
+            for role in roles {
+                state.defaults.insert((flow, *role), id.to_string());
+            }
+            flow
+        };
+
+        for role in roles {
+            self.notify(Notification::DefaultDeviceChanged {
+                id: id.to_string(),
+                flow,
+                role: *role,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl AudioBackend for FakeBackend {
+    type Endpoint = FakeEndpoint;
+
+    fn new(tx: &Sender<Notification>) -> Result<Self> {
+        Ok(Self::with_devices(tx, []))
+    }
+
+    fn get_active_audio_devices(self: &Arc<Self>) -> Result<Vec<FakeEndpoint>> {
+        let devices = self
+            .lock()?
+            .devices
+            .iter()
+            .map(|(id, device)| FakeEndpoint {
+                backend: Arc::clone(self),
+                id: id.clone(),
+                name: device.name.clone(),
+                flow: device.flow,
+                form_factor: device.form_factor,
+            })
+            .collect();
+
+        Ok(devices)
+    }
+
+    fn get_default_audio_id(&self, flow: DataFlow, role: Role) -> Result<String> {
+        self.lock()?
+            .defaults
+            .get(&(flow, role))
+            .cloned()
+            .ok_or_else(|| anyhow!("No default device for {:?} {:?}", flow, role))
+    }
+
+    fn set_app_default_device(&self, process_id: u32, device_id: Option<&str>) -> Result<()> {
+        let mut state = self.lock()?;
+        match device_id {
+            Some(id) => {
+                if !state.devices.contains_key(id) {
+                    return Err(anyhow!("No such device: {:?}", id));
+                }
+                state.app_devices.insert(process_id, id.to_string());
+            }
+            None => {
+                state.app_devices.remove(&process_id);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct FakeEndpoint {
+    backend: Arc<FakeBackend>,
+    id: String,
+    name: String,
+    flow: DataFlow,
+    form_factor: FormFactor,
+}
+
+impl AudioEndpoint for FakeEndpoint {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn flow(&self) -> DataFlow {
+        self.flow
+    }
+
+    fn form_factor(&self) -> FormFactor {
+        self.form_factor
+    }
+
+    fn set_as_default(&self, roles: &[Role]) -> Result<()> {
+        self.backend.set_default(&self.id, roles)
+    }
+
+    fn get_volume(&self) -> Result<f32> {
+        Ok(self.backend.device(&self.id)?.volume)
+    }
+
+    fn set_volume(&self, volume: f32) -> Result<()> {
+        self.backend
+            .update_device(&self.id, |device| device.volume = volume)
+    }
+
+    fn get_mute_state(&self) -> Result<bool> {
+        Ok(self.backend.device(&self.id)?.muted)
+    }
+
+    fn set_mute_state(&self, mute_state: bool) -> Result<()> {
+        self.backend
+            .update_device(&self.id, |device| device.muted = mute_state)
+    }
+
+    fn get_sessions(&self) -> Result<Vec<AudioSession>> {
+        Ok(self
+            .backend
+            .device(&self.id)?
+            .sessions
+            .into_values()
+            .collect())
+    }
+
+    fn set_session_volume(&self, process_id: u32, volume: f32) -> Result<()> {
+        self.backend
+            .update_session(&self.id, process_id, |session| session.volume = volume)
+    }
+
+    fn set_session_mute_state(&self, process_id: u32, mute_state: bool) -> Result<()> {
+        self.backend
+            .update_session(&self.id, process_id, |session| session.muted = mute_state)
+    }
+}
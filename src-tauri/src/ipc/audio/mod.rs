@@ -1,316 +1,176 @@
-mod device_changer;
+pub mod fake;
 pub mod notifier;
+#[cfg(target_os = "linux")]
+pub mod pulse;
+#[cfg(windows)]
+pub mod wasapi;
 
-// https://qiita.com/benki/items/635867b654783da0322f
-
-use anyhow::Result;
-use std::{collections::HashMap, ffi::OsString, os::windows::ffi::OsStringExt, sync::Arc};
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
-use windows::{
-    core::Interface,
-    Win32::{
-        Devices::FunctionDiscovery::PKEY_Device_FriendlyName,
-        Foundation::{CloseHandle, FALSE},
-        Media::Audio::{
-            eMultimedia, eRender, Endpoints::IAudioEndpointVolume, IAudioSessionControl,
-            IAudioSessionControl2, IAudioSessionManager2, IMMDevice, IMMDeviceEnumerator,
-            ISimpleAudioVolume, MMDeviceEnumerator, DEVICE_STATE_ACTIVE,
-        },
-        System::{
-            Com::{
-                CoCreateInstance, CoInitialize, CoUninitialize,
-                StructuredStorage::PropVariantToStringAlloc, CLSCTX_ALL, STGM_READ,
-            },
-            ProcessStatus::GetModuleBaseNameW,
-            Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
-        },
-    },
-};
-
-struct Com;
-
-impl Com {
-    pub fn new() -> Result<Self> {
-        unsafe {
-            let _ = CoInitialize(None);
-        }
-
-        Ok(Com)
-    }
-}
 
-impl Drop for Com {
-    fn drop(&mut self) {
-        unsafe {
-            CoUninitialize();
-        }
-    }
+use notifier::Notification;
+
+/// The backend used by the application on the current platform.
+#[cfg(windows)]
+pub type DefaultBackend = wasapi::Singleton;
+#[cfg(target_os = "linux")]
+pub type DefaultBackend = pulse::PulseBackend;
+
+/// Direction of an endpoint: playback devices render, microphones capture.
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum DataFlow {
+    Render,
+    Capture,
 }
 
-pub struct Singleton {
-    _com: Com,
-
-    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/mmdeviceapi/nn-mmdeviceapi-immdeviceenumerator
-    pub(crate) device_enumerator: IMMDeviceEnumerator,
-    notification_callbacks: notifier::NotificationCallbacks,
-    policy_config: device_changer::PolicyConfig,
+/// What a default device is used for, as in Windows' `ERole`.
+///
+/// Backends without roles treat every role as the same default device.
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum Role {
+    Console,
+    Multimedia,
+    Communications,
 }
 
-unsafe impl Send for Singleton {}
-unsafe impl Sync for Singleton {}
-
-impl Singleton {
-    pub fn new(tx: &Sender<notifier::Notification>) -> Result<Self> {
-        let com = Com::new()?;
-        let device_enumerator = unsafe { CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)? };
-        let notification_callbacks = notifier::NotificationCallbacks::new(tx);
-        notification_callbacks.register_to_enumerator(&device_enumerator)?;
-
-        let policy_config = device_changer::PolicyConfig::new()?;
-
-        Ok(Singleton {
-            _com: com,
-            device_enumerator,
-            notification_callbacks,
-            policy_config,
-        })
-    }
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Console, Role::Multimedia, Role::Communications];
+}
 
-    pub fn get_active_audio_devices(self: &Arc<Self>) -> Result<Vec<IMMAudioDevice>> {
-        // https://learn.microsoft.com/ja-jp/windows/win32/api/mmdeviceapi/nn-mmdeviceapi-immdevicecollection
-        let device_collection = unsafe {
-            self.device_enumerator
-                .EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?
-        };
+/// Physical kind of an endpoint, as in Windows' `EndpointFormFactor`.
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default,
+)]
+pub enum FormFactor {
+    Speakers,
+    Headphones,
+    Headset,
+    Microphone,
+    #[default]
+    Unknown,
+}
 
-        let len = unsafe { device_collection.GetCount()? };
+/// A platform audio API: device enumeration, the default device and change notifications.
+///
+/// Implementations send every change they observe to the `Sender<Notification>` they were
+/// created with, and unregister their callbacks when dropped.
+pub trait AudioBackend: Send + Sync + Sized + 'static {
+    type Endpoint: AudioEndpoint;
 
-        let devices = (0..len)
-            .map(|i| {
-                let device = unsafe { device_collection.Item(i)? };
-                IMMAudioDevice::new(Arc::clone(self), device)
-            })
-            .collect::<Result<Vec<_>>>()?;
+    fn new(tx: &Sender<Notification>) -> Result<Self>;
 
-        Ok(devices)
-    }
+    /// Lists active endpoints of both directions.
+    fn get_active_audio_devices(self: &Arc<Self>) -> Result<Vec<Self::Endpoint>>;
 
-    pub fn get_default_audio_id(&self) -> Result<String> {
-        let device = unsafe {
-            self.device_enumerator
-                .GetDefaultAudioEndpoint(eRender, eMultimedia)?
-        };
-        let id = unsafe { device.GetId()?.to_string()? };
+    fn get_default_audio_id(&self, flow: DataFlow, role: Role) -> Result<String>;
 
-        Ok(id)
+    /// Routes the audio of one process to `device_id`, or back to the default device with `None`.
+    fn set_app_default_device(&self, process_id: u32, _device_id: Option<&str>) -> Result<()> {
+        Err(anyhow!(
+            "Per-application routing is not supported (pid: {})",
+            process_id
+        ))
     }
 }
 
-impl Drop for Singleton {
-    fn drop(&mut self) {
-        self.notification_callbacks
-            .unregister_to_enumerator(&self.device_enumerator)
-            .unwrap();
-    }
+/// An application playing audio on an endpoint, as listed by the volume mixer.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct AudioSession {
+    pub pid: u32,
+    pub name: String,
+    pub volume: f32,
+    pub muted: bool,
 }
 
-fn get_name_from_immdevice(device: &IMMDevice) -> Result<String> {
-    let property_store = unsafe { device.OpenPropertyStore(STGM_READ)? };
-    let name_propvariant = unsafe { property_store.GetValue(&PKEY_Device_FriendlyName)? };
-    let name = unsafe { PropVariantToStringAlloc(&name_propvariant)?.to_string()? };
-
-    Ok(name)
+/// Volume range of an endpoint in decibels, as in `IAudioEndpointVolume::GetVolumeRange`.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeRange {
+    pub min_db: f32,
+    pub max_db: f32,
+    pub increment_db: f32,
 }
 
-pub struct IMMAudioDevice {
-    is: Arc<Singleton>,
-
-    pub id: String,
-    pub name: String,
-
-    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/mmdeviceapi/nn-mmdeviceapi-immdevice
-    _device: IMMDevice,
-
-    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/endpointvolume/nn-endpointvolume-iaudioendpointvolume
-    pub(crate) endpoint_volume: IAudioEndpointVolume,
-
-    pub(crate) session_control_map: HashMap<u32, IAudioSessionControl>,
+/// Peak sample values from `0.0` to `1.0` since the previous read.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct PeakLevels {
+    pub peak: f32,
+    /// One value per channel, in the device's channel order.
+    pub channels: Vec<f32>,
 }
 
-unsafe impl Send for IMMAudioDevice {}
-unsafe impl Sync for IMMAudioDevice {}
-
-impl IMMAudioDevice {
-    pub fn new(is: Arc<Singleton>, device: IMMDevice) -> Result<Self> {
-        let id = unsafe { device.GetId()?.to_string()? };
-        let name = get_name_from_immdevice(&device)?;
-
-        // https://learn.microsoft.com/ja-jp/windows/win32/api/mmdeviceapi/nf-mmdeviceapi-immdevice-activate
-        // https://learn.microsoft.com/ja-jp/windows/win32/api/endpointvolume/nn-endpointvolume-iaudioendpointvolume
-        let endpoint_volume: IAudioEndpointVolume = unsafe { device.Activate(CLSCTX_ALL, None)? };
-
-        let mut session_control_map: HashMap<u32, IAudioSessionControl> = HashMap::new();
-
-        #[cfg(debug_assertions)]
-        unsafe {
-            let session_manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
-            let sessions = session_manager.GetSessionEnumerator()?;
-
-            for i in 0..sessions.GetCount()? {
-                let session_control: IAudioSessionControl = sessions.GetSession(i)?;
-                let session_control2: IAudioSessionControl2 = session_control.cast().unwrap();
-                let process_id = session_control2.GetProcessId()?;
-
-                session_control_map.insert(process_id, session_control);
-
-                // let mut process_name = "Unknown".to_string();
-
-                // if process_id == 0 {
-                //     process_name = "System Sounds".to_string();
-                // } else if let Ok(name) = get_process_name_by_id(process_id) {
-                //     process_name = name;
-                // }
-
-                // let audio_volume: ISimpleAudioVolume = session_control.cast().unwrap();
-                // let mut volume = 0.0;
-                // let mut mute = false;
-
-                // if let Ok(v) = audio_volume.GetMasterVolume() {
-                //     volume = v;
-                // }
-
-                // if let Ok(m) = audio_volume.GetMute() {
-                //     mute = m.as_bool();
-                // }
-
-                // println!(
-                //     "Session: {} (PID: {}), Volume: {}, Mute: {}",
-                //     process_name, process_id, volume, mute
-                // );
-            }
-        }
-
-        is.notification_callbacks
-            .register_to_volume(&endpoint_volume)?;
-
-        Ok(IMMAudioDevice {
-            id,
-            name,
-            _device: device,
-            endpoint_volume,
-            is,
-            session_control_map,
-        })
-    }
+/// A single output device handed out by an `AudioBackend`.
+pub trait AudioEndpoint: Send + Sync + 'static {
+    fn id(&self) -> &str;
 
-    pub(crate) fn get_session(&self, process_id: u32) -> Result<IAudioSessionControl> {
-        let session_control = self.session_control_map.get(&process_id).unwrap();
-        Ok(session_control.clone())
-    }
+    fn name(&self) -> &str;
 
-    pub(crate) fn get_session_audio_volume(&self, process_id: u32) -> Result<ISimpleAudioVolume> {
-        let session_control = self.get_session(process_id)?;
-        let audio_volume: ISimpleAudioVolume = session_control.cast().unwrap();
-        Ok(audio_volume)
-    }
+    fn flow(&self) -> DataFlow;
 
-    pub fn set_as_default(&self) -> Result<()> {
-        self.is.policy_config.set_default_endpoint(&self.id)?;
-
-        Ok(())
+    fn form_factor(&self) -> FormFactor {
+        FormFactor::Unknown
     }
 
-    pub fn get_volume(&self) -> Result<f32> {
-        let volume = unsafe { self.endpoint_volume.GetMasterVolumeLevelScalar()? };
+    /// Makes this endpoint the default device of its direction for each of `roles`.
+    fn set_as_default(&self, roles: &[Role]) -> Result<()>;
 
-        Ok(volume)
-    }
+    fn get_volume(&self) -> Result<f32>;
 
-    pub fn get_session_volume(&self, process_id: u32) -> Result<f32> {
-        let audio_volume = self.get_session_audio_volume(process_id)?;
-        let volume = unsafe { audio_volume.GetMasterVolume()? };
+    fn set_volume(&self, volume: f32) -> Result<()>;
 
-        Ok(volume)
+    fn get_volume_db(&self) -> Result<f32> {
+        Err(anyhow!("Volume in dB is not supported: {:?}", self.id()))
     }
 
-    pub fn get_mute_state(&self) -> Result<bool> {
-        let mute_state = unsafe { self.endpoint_volume.GetMute()?.as_bool() };
-
-        Ok(mute_state)
+    fn set_volume_db(&self, _db: f32) -> Result<()> {
+        Err(anyhow!("Volume in dB is not supported: {:?}", self.id()))
     }
 
-    pub fn get_session_mute_state(&self, process_id: u32) -> Result<bool> {
-        let audio_volume = self.get_session_audio_volume(process_id)?;
-        let mute_state = unsafe { audio_volume.GetMute()?.as_bool() };
-
-        Ok(mute_state)
+    /// The dB value `volume` (`0.0` to `1.0`) stands for.
+    ///
+    /// Backends which can only find out by setting it put the previous volume back.
+    fn volume_to_db(&self, _volume: f32) -> Result<f32> {
+        Err(anyhow!(
+            "Converting volume to dB is not supported: {:?}",
+            self.id()
+        ))
     }
 
-    pub fn set_volume(&self, volume: f32) -> Result<()> {
-        unsafe {
-            self.endpoint_volume
-                .SetMasterVolumeLevelScalar(volume, std::ptr::null())?;
-        }
-
-        Ok(())
+    fn get_volume_range(&self) -> Result<VolumeRange> {
+        Err(anyhow!("Volume range is not supported: {:?}", self.id()))
     }
 
-    pub fn set_session_volume(&self, process_id: u32, volume: f32) -> Result<()> {
-        let audio_volume = self.get_session_audio_volume(process_id)?;
-        unsafe {
-            audio_volume.SetMasterVolume(volume, std::ptr::null())?;
-        }
-
-        Ok(())
+    /// Moves the volume one step of the device's own volume control up or down.
+    fn volume_step(&self, _up: bool) -> Result<()> {
+        Err(anyhow!(
+            "Hardware volume steps are not supported: {:?}",
+            self.id()
+        ))
     }
 
-    pub fn set_mute_state(&self, mute_state: bool) -> Result<()> {
-        unsafe {
-            self.endpoint_volume.SetMute(mute_state, std::ptr::null())?;
-        }
+    fn get_mute_state(&self) -> Result<bool>;
 
-        Ok(())
-    }
+    fn set_mute_state(&self, mute_state: bool) -> Result<()>;
 
-    pub fn set_session_mute_state(&self, process_id: u32, mute_state: bool) -> Result<()> {
-        let audio_volume = self.get_session_audio_volume(process_id)?;
-        unsafe {
-            audio_volume.SetMute(mute_state, std::ptr::null())?;
-        }
-
-        Ok(())
+    fn get_peak_levels(&self) -> Result<PeakLevels> {
+        Err(anyhow!("Metering is not supported: {:?}", self.id()))
     }
-}
 
-impl Drop for IMMAudioDevice {
-    fn drop(&mut self) {
-        self.is
-            .notification_callbacks
-            .unregister_to_volume(&self.endpoint_volume)
-            .unwrap();
+    /// Backends without per-application streams report no sessions.
+    fn get_sessions(&self) -> Result<Vec<AudioSession>> {
+        Ok(Vec::new())
     }
-}
 
-unsafe fn get_process_name_by_id(process_id: u32) -> Result<String> {
-    let try_process_handle = OpenProcess(
-        PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
-        FALSE,
-        process_id,
-    );
-
-    if let Err(e) = try_process_handle {
-        return Err(anyhow::anyhow!("Failed to open process: {}", e));
+    fn set_session_volume(&self, process_id: u32, _volume: f32) -> Result<()> {
+        Err(anyhow!("No such session: {}", process_id))
     }
 
-    let process_handle = try_process_handle.unwrap();
-
-    let mut buffer = [0; 1024];
-    let len = GetModuleBaseNameW(process_handle, None, &mut buffer);
-
-    let os_string = OsString::from_wide(&buffer[..len as usize]);
-    let process_name = os_string.to_string_lossy().into_owned();
-
-    CloseHandle(process_handle).unwrap();
-
-    Ok(process_name)
+    fn set_session_mute_state(&self, process_id: u32, _mute_state: bool) -> Result<()> {
+        Err(anyhow!("No such session: {}", process_id))
+    }
 }
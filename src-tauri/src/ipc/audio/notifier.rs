@@ -1,32 +1,15 @@
-use anyhow::Result;
-use std::fmt::Debug;
-use tokio::sync::mpsc::Sender;
-use windows::{
-    core::{implement, PCWSTR},
-    Win32::{
-        Foundation::{ERROR_ACCESS_DENIED, ERROR_INVALID_DATA, WIN32_ERROR},
-        Media::Audio::{
-            EDataFlow, ERole,
-            Endpoints::{
-                IAudioEndpointVolume, IAudioEndpointVolumeCallback,
-                IAudioEndpointVolumeCallback_Impl,
-            },
-            IMMDeviceEnumerator, IMMNotificationClient, IMMNotificationClient_Impl,
-            AUDIO_VOLUME_NOTIFICATION_DATA, DEVICE_STATE,
-        },
-        UI::Shell::PropertiesSystem::PROPERTYKEY,
-    },
-};
+use super::{DataFlow, Role};
 
-fn to_win_error<E: Debug>(e: E, code: WIN32_ERROR) -> windows::core::Error {
-    windows::core::Error::new::<String>(code.to_hresult(), format!("{:?}", e).into())
-}
+/// `state` of `DeviceStateChanged` when the device became usable, as `DEVICE_STATE_ACTIVE`.
+pub const DEVICE_STATE_ACTIVE: u32 = 0x1;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum Notification {
     DefaultDeviceChanged {
         id: String,
+        flow: DataFlow,
+        role: Role,
     },
     DeviceAdded {
         id: String,
@@ -47,183 +30,52 @@ pub enum Notification {
         volume: f32,
         muted: bool,
     },
+    SessionCreated {
+        id: String,
+        pid: u32,
+    },
     SessionVolumeChanged {
         id: String,
+        pid: u32,
         volume: f32,
         muted: bool,
     },
+    SessionStateChanged {
+        id: String,
+        pid: u32,
+        state: u32,
+    },
+    SessionDisconnected {
+        id: String,
+        pid: u32,
+        reason: u32,
+    },
 }
 
-#[implement(IMMNotificationClient)]
-struct AppEventHandlerClient(Sender<Notification>);
-
-impl IMMNotificationClient_Impl for AppEventHandlerClient {
-    fn OnDeviceStateChanged(
-        &self,
-        pwstrdeviceid: &PCWSTR,
-        dwnewstate: DEVICE_STATE,
-    ) -> windows::core::Result<()> {
-        unsafe {
-            self.0
-                .blocking_send(Notification::DeviceStateChanged {
-                    // .send(Notification::DeviceStateChanged {
-                    id: pwstrdeviceid
-                        .to_string()
-                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
-                    state: dwnewstate.0,
-                })
-                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
-        }
-
-        Ok(())
-    }
-
-    fn OnDeviceAdded(&self, pwstrdeviceid: &PCWSTR) -> windows::core::Result<()> {
-        unsafe {
-            self.0
-                .blocking_send(Notification::DeviceAdded {
-                    // .send(Notification::DeviceAdded {
-                    id: pwstrdeviceid
-                        .to_string()
-                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
-                })
-                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
-        }
-
-        Ok(())
-    }
-
-    fn OnDeviceRemoved(&self, pwstrdeviceid: &PCWSTR) -> windows::core::Result<()> {
-        unsafe {
-            self.0
-                .blocking_send(Notification::DeviceRemoved {
-                    // .send(Notification::DeviceRemoved {
-                    id: pwstrdeviceid
-                        .to_string()
-                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
-                })
-                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
-        }
-
-        Ok(())
-    }
-
-    fn OnDefaultDeviceChanged(
-        &self,
-        _flow: EDataFlow,
-        _role: ERole,
-        pwstrdefaultdeviceid: &PCWSTR,
-    ) -> windows::core::Result<()> {
-        unsafe {
-            self.0
-                .blocking_send(Notification::DefaultDeviceChanged {
-                    // .send(Notification::DefaultDeviceChanged {
-                    id: pwstrdefaultdeviceid
-                        .to_string()
-                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
-                })
-                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
-        }
-
-        Ok(())
-    }
-
-    fn OnPropertyValueChanged(
-        &self,
-        pwstrdeviceid: &PCWSTR,
-        key: &PROPERTYKEY,
-    ) -> windows::core::Result<()> {
-        unsafe {
-            self.0
-                .blocking_send(Notification::PropertyValueChanged {
-                    // .send(Notification::PropertyValueChanged {
-                    id: pwstrdeviceid
-                        .to_string()
-                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
-                    key: format!("{:?}", key.fmtid),
-                })
-                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
-        }
-
-        Ok(())
-    }
-}
-
-#[implement(IAudioEndpointVolumeCallback)]
-struct AudioEndpointVolumeCallback(Sender<Notification>);
-
-impl IAudioEndpointVolumeCallback_Impl for AudioEndpointVolumeCallback {
-    fn OnNotify(&self, data: *mut AUDIO_VOLUME_NOTIFICATION_DATA) -> windows::core::Result<()> {
-        unsafe {
-            if data == std::ptr::null_mut() {
-                return Err(to_win_error("data is null", ERROR_INVALID_DATA));
-            }
-
-            self.0
-                .blocking_send(Notification::VolumeChanged {
-                    // .send(Notification::VolumeChanged {
-                    id: format!("{:?}", (*data).guidEventContext),
-                    volume: (*data).fMasterVolume,
-                    muted: (*data).bMuted.as_bool(),
-                })
-                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
-        }
-
-        Ok(())
-    }
-}
-
-pub(crate) struct NotificationCallbacks {
-    notification_client: IMMNotificationClient,
-    endpoint_volume_callback: IAudioEndpointVolumeCallback,
-}
-
-impl NotificationCallbacks {
-    pub(crate) fn new(tx: &Sender<Notification>) -> Self {
-        let notification_client = AppEventHandlerClient(tx.clone()).into();
-        let endpoint_volume_callback = AudioEndpointVolumeCallback(tx.clone()).into();
-
-        Self {
-            notification_client,
-            endpoint_volume_callback,
-        }
-    }
-
-    pub(crate) fn register_to_enumerator(
-        &self,
-        device_enumerator: &IMMDeviceEnumerator,
-    ) -> Result<()> {
-        unsafe {
-            device_enumerator.RegisterEndpointNotificationCallback(&self.notification_client)?;
-        }
-
-        Ok(())
+impl Notification {
+    /// Session notifications only change the per-application list of a device, so they can be
+    /// handled without enumerating the devices again.
+    pub fn is_session(&self) -> bool {
+        matches!(
+            self,
+            Notification::SessionCreated { .. }
+                | Notification::SessionVolumeChanged { .. }
+                | Notification::SessionStateChanged { .. }
+                | Notification::SessionDisconnected { .. }
+        )
     }
 
-    pub(crate) fn unregister_to_enumerator(
-        &self,
-        device_enumerator: &IMMDeviceEnumerator,
-    ) -> Result<()> {
-        unsafe {
-            device_enumerator.UnregisterEndpointNotificationCallback(&self.notification_client)?;
-        }
-
-        Ok(())
-    }
-
-    pub(crate) fn register_to_volume(&self, volume: &IAudioEndpointVolume) -> Result<()> {
-        unsafe {
-            volume.RegisterControlChangeNotify(&self.endpoint_volume_callback)?;
-        }
-
-        Ok(())
-    }
-
-    pub(crate) fn unregister_to_volume(&self, volume: &IAudioEndpointVolume) -> Result<()> {
-        unsafe {
-            volume.UnregisterControlChangeNotify(&self.endpoint_volume_callback)?;
-        }
-
-        Ok(())
+    /// Changes the safeguard and the fallback react to, which should not wait for the rest of a
+    /// batch while audio may already be playing on another output.
+    pub fn is_urgent(&self) -> bool {
+        matches!(
+            self,
+            Notification::DeviceRemoved { .. }
+                | Notification::DeviceStateChanged { .. }
+                | Notification::DefaultDeviceChanged {
+                    flow: DataFlow::Render,
+                    ..
+                }
+        )
     }
 }
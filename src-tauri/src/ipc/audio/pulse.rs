@@ -0,0 +1,771 @@
+// https://docs.rs/libpulse-binding/latest/libpulse_binding/mainloop/threaded/index.html
+
+use anyhow::{anyhow, Result};
+use libpulse_binding as pulse;
+use pulse::{
+    callbacks::ListResult,
+    context::{
+        introspect::{Introspector, ServerInfo, SinkInfo, SourceInfo},
+        subscribe::{Facility, InterestMaskSet, Operation as SubscribeOperation},
+        Context, FlagSet as ContextFlagSet, State as ContextState,
+    },
+    mainloop::threaded::Mainloop,
+    operation::{Operation, State as OperationState},
+    volume::{ChannelVolumes, Volume, VolumeDB},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc::Sender;
+
+use super::{notifier::Notification, AudioBackend, AudioEndpoint, DataFlow, FormFactor, Role};
+
+const APPLICATION_NAME: &str = "OutputSwitcher";
+
+/// Raw handle used by callbacks on the PulseAudio thread to wake up a waiting caller.
+#[derive(Clone, Copy)]
+struct MainloopSignal(*mut Mainloop);
+
+impl MainloopSignal {
+    fn signal(&self) {
+        unsafe {
+            (*self.0).signal(false);
+        }
+    }
+}
+
+/// Lets sink and source operations, whose callback types differ, go through one `request`.
+trait PendingOperation {
+    fn get_state(&self) -> OperationState;
+}
+
+impl<T: ?Sized> PendingOperation for Operation<T> {
+    fn get_state(&self) -> OperationState {
+        Operation::get_state(self)
+    }
+}
+
+struct AnyOperation(Box<dyn PendingOperation>);
+
+impl PendingOperation for AnyOperation {
+    fn get_state(&self) -> OperationState {
+        self.0.get_state()
+    }
+}
+
+impl<T: ?Sized + 'static> From<Operation<T>> for AnyOperation {
+    fn from(operation: Operation<T>) -> Self {
+        AnyOperation(Box::new(operation))
+    }
+}
+
+fn to_scalar(volume: Volume) -> f32 {
+    volume.0 as f32 / Volume::NORMAL.0 as f32
+}
+
+fn from_scalar(volume: f32) -> Volume {
+    Volume((volume.clamp(0.0, 1.0) * Volume::NORMAL.0 as f32).round() as u32)
+}
+
+/// Never blocks, as it runs in callbacks holding the mainloop lock a waiting request needs.
+fn send(tx: &Sender<Notification>, notification: Notification) {
+    if let Err(e) = tx.try_send(notification) {
+        log::error!("PulseBackend failed to notify: {:?}", e);
+    }
+}
+
+/// The parts of `SinkInfo` and `SourceInfo` the backend cares about.
+#[derive(Debug, Clone)]
+struct DeviceInfo {
+    index: u32,
+    id: String,
+    name: String,
+    form_factor: FormFactor,
+    volume: ChannelVolumes,
+    muted: bool,
+}
+
+impl DeviceInfo {
+    fn from_sink(info: &SinkInfo) -> Option<Self> {
+        let id = info.name.as_ref()?.to_string();
+        let name = info
+            .description
+            .as_ref()
+            .map(|d| d.to_string())
+            .unwrap_or_else(|| id.clone());
+
+        Some(Self {
+            index: info.index,
+            id,
+            name,
+            form_factor: to_form_factor(info.proplist.get_str("device.form_factor")),
+            volume: info.volume,
+            muted: info.mute,
+        })
+    }
+
+    /// Monitor sources mirror a sink and are not microphones, so they are skipped.
+    fn from_source(info: &SourceInfo) -> Option<Self> {
+        if info.monitor_of_sink.is_some() {
+            return None;
+        }
+
+        let id = info.name.as_ref()?.to_string();
+        let name = info
+            .description
+            .as_ref()
+            .map(|d| d.to_string())
+            .unwrap_or_else(|| id.clone());
+
+        Some(Self {
+            index: info.index,
+            id,
+            name,
+            form_factor: to_form_factor(info.proplist.get_str("device.form_factor")),
+            volume: info.volume,
+            muted: info.mute,
+        })
+    }
+}
+
+// https://www.freedesktop.org/wiki/Software/PulseAudio/Documentation/Developer/Clients/ApplicationProperties/
+fn to_form_factor(form_factor: Option<String>) -> FormFactor {
+    match form_factor.as_deref() {
+        Some("speaker") => FormFactor::Speakers,
+        Some("headphone") => FormFactor::Headphones,
+        Some("headset") | Some("handset") => FormFactor::Headset,
+        Some("microphone") | Some("webcam") => FormFactor::Microphone,
+        _ => FormFactor::Unknown,
+    }
+}
+
+fn default_name(info: &ServerInfo, flow: DataFlow) -> Option<String> {
+    let name = match flow {
+        DataFlow::Render => info.default_sink_name.as_ref(),
+        DataFlow::Capture => info.default_source_name.as_ref(),
+    };
+
+    name.map(|n| n.to_string())
+}
+
+/// Device index to device name, since `Removed` events only carry the index.
+type DeviceNames = Arc<Mutex<HashMap<(DataFlow, u32), String>>>;
+
+struct Connection {
+    mainloop: Box<Mainloop>,
+    context: Box<Context>,
+}
+
+/// Backend talking to a PulseAudio server (or PipeWire through pipewire-pulse).
+///
+/// Sinks are render devices and sources are capture devices. Their ids are the sink and
+/// source names, which stay stable across reconnects unlike their indices.
+pub struct PulseBackend {
+    connection: Mutex<Connection>,
+    signal: MainloopSignal,
+    device_names: DeviceNames,
+}
+
+unsafe impl Send for PulseBackend {}
+unsafe impl Sync for PulseBackend {}
+
+impl PulseBackend {
+    /// Runs `f` with the mainloop locked and blocks until the returned operation completes.
+    ///
+    /// `f` receives the slot its callback should fill and the signal it must raise when done.
+    fn request<R, O, F>(&self, f: F) -> Result<R>
+    where
+        R: Default + 'static,
+        O: PendingOperation,
+        F: FnOnce(&mut Context, Arc<Mutex<R>>, MainloopSignal) -> O,
+    {
+        let mut connection = self
+            .connection
+            .lock()
+            .map_err(|_| anyhow!("PulseBackend connection is poisoned"))?;
+        let Connection { mainloop, context } = &mut *connection;
+
+        let slot = Arc::new(Mutex::new(R::default()));
+
+        mainloop.lock();
+        let operation = f(context, Arc::clone(&slot), self.signal);
+        while operation.get_state() == OperationState::Running {
+            mainloop.wait();
+        }
+        let state = operation.get_state();
+        mainloop.unlock();
+
+        if state != OperationState::Done {
+            return Err(anyhow!("PulseAudio operation was cancelled"));
+        }
+
+        let result = std::mem::take(
+            &mut *slot
+                .lock()
+                .map_err(|_| anyhow!("PulseBackend result is poisoned"))?,
+        );
+
+        Ok(result)
+    }
+
+    fn get_device_list(&self, flow: DataFlow) -> Result<Vec<DeviceInfo>> {
+        self.request(|context, slot, signal| -> AnyOperation {
+            let push = move |info: Option<DeviceInfo>| {
+                if let (Some(info), Ok(mut slot)) = (info, slot.lock()) {
+                    slot.push(info);
+                }
+            };
+
+            match flow {
+                DataFlow::Render => context
+                    .introspect()
+                    .get_sink_info_list(move |result| match result {
+                        ListResult::Item(info) => push(DeviceInfo::from_sink(info)),
+                        ListResult::End | ListResult::Error => signal.signal(),
+                    })
+                    .into(),
+                DataFlow::Capture => context
+                    .introspect()
+                    .get_source_info_list(move |result| match result {
+                        ListResult::Item(info) => push(DeviceInfo::from_source(info)),
+                        ListResult::End | ListResult::Error => signal.signal(),
+                    })
+                    .into(),
+            }
+        })
+    }
+
+    fn get_device(&self, id: &str, flow: DataFlow) -> Result<DeviceInfo> {
+        let device: Option<DeviceInfo> = self.request(|context, slot, signal| -> AnyOperation {
+            let set = move |info: Option<DeviceInfo>| {
+                if let Ok(mut slot) = slot.lock() {
+                    *slot = info;
+                }
+            };
+
+            match flow {
+                DataFlow::Render => context
+                    .introspect()
+                    .get_sink_info_by_name(id, move |result| match result {
+                        ListResult::Item(info) => set(DeviceInfo::from_sink(info)),
+                        ListResult::End | ListResult::Error => signal.signal(),
+                    })
+                    .into(),
+                DataFlow::Capture => context
+                    .introspect()
+                    .get_source_info_by_name(id, move |result| match result {
+                        ListResult::Item(info) => set(DeviceInfo::from_source(info)),
+                        ListResult::End | ListResult::Error => signal.signal(),
+                    })
+                    .into(),
+            }
+        })?;
+
+        device.ok_or_else(|| anyhow!("No such device: {:?}", id))
+    }
+
+    fn expect_success(&self, success: bool, what: &str) -> Result<()> {
+        if !success {
+            let connection = self
+                .connection
+                .lock()
+                .map_err(|_| anyhow!("PulseBackend connection is poisoned"))?;
+            return Err(anyhow!("{} failed: {:?}", what, connection.context.errno()));
+        }
+
+        Ok(())
+    }
+}
+
+fn success_callback(
+    slot: Arc<Mutex<bool>>,
+    signal: MainloopSignal,
+) -> Option<Box<dyn FnMut(bool) + 'static>> {
+    Some(Box::new(move |success| {
+        if let Ok(mut slot) = slot.lock() {
+            *slot = success;
+        }
+        signal.signal();
+    }))
+}
+
+/// Announces the device at `index` once its name is known.
+fn on_device_event(
+    introspector: &Introspector,
+    tx: &Sender<Notification>,
+    device_names: &DeviceNames,
+    flow: DataFlow,
+    index: u32,
+    is_new: bool,
+) {
+    let tx = tx.clone();
+    let device_names = Arc::clone(device_names);
+
+    let announce = move |info: Option<DeviceInfo>| {
+        let Some(info) = info else {
+            return;
+        };
+
+        if let Ok(mut names) = device_names.lock() {
+            names.insert((flow, info.index), info.id.clone());
+        }
+
+        let notification = if is_new {
+            Notification::DeviceAdded { id: info.id }
+        } else {
+            Notification::VolumeChanged {
+                id: info.id,
+                volume: to_scalar(info.volume.max()),
+                muted: info.muted,
+            }
+        };
+        send(&tx, notification);
+    };
+
+    match flow {
+        DataFlow::Render => {
+            introspector.get_sink_info_by_index(index, move |result| {
+                if let ListResult::Item(info) = result {
+                    announce(DeviceInfo::from_sink(info));
+                }
+            });
+        }
+        DataFlow::Capture => {
+            introspector.get_source_info_by_index(index, move |result| {
+                if let ListResult::Item(info) = result {
+                    announce(DeviceInfo::from_source(info));
+                }
+            });
+        }
+    }
+}
+
+impl AudioBackend for PulseBackend {
+    type Endpoint = PulseDevice;
+
+    fn new(tx: &Sender<Notification>) -> Result<Self> {
+        let mut mainloop =
+            Box::new(Mainloop::new().ok_or_else(|| anyhow!("Failed to create mainloop"))?);
+        let mut context = Box::new(
+            Context::new(&*mainloop, APPLICATION_NAME)
+                .ok_or_else(|| anyhow!("Failed to create context"))?,
+        );
+        let signal = MainloopSignal(&mut *mainloop);
+
+        context.set_state_callback(Some(Box::new(move || signal.signal())));
+        context.connect(None, ContextFlagSet::NOFLAGS, None)?;
+
+        mainloop.lock();
+        if let Err(e) = mainloop.start() {
+            mainloop.unlock();
+            return Err(e.into());
+        }
+
+        loop {
+            match context.get_state() {
+                ContextState::Ready => break,
+                ContextState::Failed | ContextState::Terminated => {
+                    mainloop.unlock();
+                    mainloop.stop();
+                    return Err(anyhow!("Failed to connect to the PulseAudio server"));
+                }
+                _ => mainloop.wait(),
+            }
+        }
+        context.set_state_callback(None);
+
+        let introspector = context.introspect();
+        let device_names: DeviceNames = Arc::default();
+        let defaults: Arc<Mutex<HashMap<DataFlow, String>>> = Arc::default();
+        let callback_device_names = Arc::clone(&device_names);
+        let tx = tx.clone();
+
+        context.set_subscribe_callback(Some(Box::new(
+            move |facility: Option<Facility>, operation: Option<SubscribeOperation>, index: u32| {
+                let flow = match facility {
+                    Some(Facility::Sink) => DataFlow::Render,
+                    Some(Facility::Source) => DataFlow::Capture,
+                    Some(Facility::Server) => {
+                        let tx = tx.clone();
+                        let defaults = Arc::clone(&defaults);
+
+                        introspector.get_server_info(move |info| {
+                            let Ok(mut defaults) = defaults.lock() else {
+                                return;
+                            };
+
+                            for flow in [DataFlow::Render, DataFlow::Capture] {
+                                let Some(id) = default_name(info, flow) else {
+                                    continue;
+                                };
+
+                                if defaults.get(&flow) == Some(&id) {
+                                    continue;
+                                }
+                                defaults.insert(flow, id.clone());
+
+                                // PulseAudio has a single default per direction
+                                for role in Role::ALL {
+                                    send(
+                                        &tx,
+                                        Notification::DefaultDeviceChanged {
+                                            id: id.clone(),
+                                            flow,
+                                            role,
+                                        },
+                                    );
+                                }
+                            }
+                        });
+                        return;
+                    }
+                    _ => return,
+                };
+
+                match operation {
+                    Some(SubscribeOperation::Removed) => {
+                        let id = callback_device_names
+                            .lock()
+                            .ok()
+                            .and_then(|mut n| n.remove(&(flow, index)));
+                        if let Some(id) = id {
+                            send(&tx, Notification::DeviceRemoved { id });
+                        }
+                    }
+                    Some(operation) => on_device_event(
+                        &introspector,
+                        &tx,
+                        &callback_device_names,
+                        flow,
+                        index,
+                        operation == SubscribeOperation::New,
+                    ),
+                    None => (),
+                }
+            },
+        )));
+        context.subscribe(
+            InterestMaskSet::SINK | InterestMaskSet::SOURCE | InterestMaskSet::SERVER,
+            |_| (),
+        );
+
+        mainloop.unlock();
+
+        Ok(PulseBackend {
+            connection: Mutex::new(Connection { mainloop, context }),
+            signal,
+            device_names,
+        })
+    }
+
+    fn get_active_audio_devices(self: &Arc<Self>) -> Result<Vec<PulseDevice>> {
+        let mut devices = Vec::new();
+        let mut names = HashMap::new();
+
+        for flow in [DataFlow::Render, DataFlow::Capture] {
+            for info in self.get_device_list(flow)? {
+                names.insert((flow, info.index), info.id.clone());
+                devices.push(PulseDevice {
+                    backend: Arc::clone(self),
+                    id: info.id,
+                    name: info.name,
+                    flow,
+                    form_factor: info.form_factor,
+                });
+            }
+        }
+
+        if let Ok(mut device_names) = self.device_names.lock() {
+            *device_names = names;
+        }
+
+        Ok(devices)
+    }
+
+    fn get_default_audio_id(&self, flow: DataFlow, _role: Role) -> Result<String> {
+        let default: Option<String> = self.request(|context, slot, signal| {
+            context.introspect().get_server_info(move |info| {
+                if let Ok(mut slot) = slot.lock() {
+                    *slot = default_name(info, flow);
+                }
+                signal.signal();
+            })
+        })?;
+
+        let id = default.ok_or_else(|| anyhow!("No default device for {:?}", flow))?;
+
+        // the monitor of a sink may be the default source, but it is not listed as a device
+        if flow == DataFlow::Capture && self.get_device(&id, flow).is_err() {
+            return Err(anyhow!("No default device for {:?}", flow));
+        }
+
+        Ok(id)
+    }
+}
+
+impl Drop for PulseBackend {
+    fn drop(&mut self) {
+        if let Ok(connection) = self.connection.get_mut() {
+            connection.mainloop.lock();
+            connection.context.set_subscribe_callback(None);
+            connection.context.disconnect();
+            connection.mainloop.unlock();
+            connection.mainloop.stop();
+        }
+    }
+}
+
+pub struct PulseDevice {
+    backend: Arc<PulseBackend>,
+    id: String,
+    name: String,
+    flow: DataFlow,
+    form_factor: FormFactor,
+}
+
+impl AudioEndpoint for PulseDevice {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn flow(&self) -> DataFlow {
+        self.flow
+    }
+
+    fn form_factor(&self) -> FormFactor {
+        self.form_factor
+    }
+
+    /// Roles are ignored since PulseAudio has a single default per direction.
+    fn set_as_default(&self, _roles: &[Role]) -> Result<()> {
+        let success = self.backend.request(|context, slot, signal| {
+            let callback = move |success| {
+                if let Ok(mut slot) = slot.lock() {
+                    *slot = success;
+                }
+                signal.signal();
+            };
+
+            match self.flow {
+                DataFlow::Render => context.set_default_sink(&self.id, callback),
+                DataFlow::Capture => context.set_default_source(&self.id, callback),
+            }
+        })?;
+
+        self.backend.expect_success(success, "set_default_device")
+    }
+
+    fn get_volume(&self) -> Result<f32> {
+        let info = self.backend.get_device(&self.id, self.flow)?;
+
+        Ok(to_scalar(info.volume.max()))
+    }
+
+    fn set_volume(&self, volume: f32) -> Result<()> {
+        let mut channel_volumes = self.backend.get_device(&self.id, self.flow)?.volume;
+        // keep the balance between channels and only move the loudest one to `volume`
+        channel_volumes
+            .scale(from_scalar(volume))
+            .ok_or_else(|| anyhow!("Invalid volume: {}", volume))?;
+
+        let success = self.backend.request(|context, slot, signal| {
+            let callback = success_callback(slot, signal);
+            let mut introspector = context.introspect();
+
+            match self.flow {
+                DataFlow::Render => {
+                    introspector.set_sink_volume_by_name(&self.id, &channel_volumes, callback)
+                }
+                DataFlow::Capture => {
+                    introspector.set_source_volume_by_name(&self.id, &channel_volumes, callback)
+                }
+            }
+        })?;
+
+        self.backend.expect_success(success, "set_volume_by_name")
+    }
+
+    /// Pulse maps its volume to dB with a cubic curve, so this is not the linear scalar.
+    fn get_volume_db(&self) -> Result<f32> {
+        let info = self.backend.get_device(&self.id, self.flow)?;
+
+        Ok(VolumeDB::from(info.volume.max()).0 as f32)
+    }
+
+    fn set_volume_db(&self, db: f32) -> Result<()> {
+        self.set_volume(to_scalar(Volume::from(VolumeDB(db as f64))))
+    }
+
+    fn volume_to_db(&self, volume: f32) -> Result<f32> {
+        Ok(VolumeDB::from(from_scalar(volume)).0 as f32)
+    }
+
+    fn get_mute_state(&self) -> Result<bool> {
+        let info = self.backend.get_device(&self.id, self.flow)?;
+
+        Ok(info.muted)
+    }
+
+    fn set_mute_state(&self, mute_state: bool) -> Result<()> {
+        let success = self.backend.request(|context, slot, signal| {
+            let callback = success_callback(slot, signal);
+            let mut introspector = context.introspect();
+
+            match self.flow {
+                DataFlow::Render => {
+                    introspector.set_sink_mute_by_name(&self.id, mute_state, callback)
+                }
+                DataFlow::Capture => {
+                    introspector.set_source_mute_by_name(&self.id, mute_state, callback)
+                }
+            }
+        })?;
+
+        self.backend.expect_success(success, "set_mute_by_name")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        process::Command,
+        time::{Duration, Instant},
+    };
+    use tokio::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+
+    const SINK: &str = "output_switcher_test";
+
+    #[test]
+    fn converts_volumes_both_ways() {
+        assert_eq!(to_scalar(Volume::NORMAL), 1.0);
+        assert_eq!(to_scalar(Volume::MUTED), 0.0);
+        assert!((to_scalar(from_scalar(0.42)) - 0.42).abs() < 0.0001);
+
+        // boosted volumes are not set from the app
+        assert_eq!(from_scalar(1.5), Volume::NORMAL);
+        assert_eq!(from_scalar(-0.5), Volume::MUTED);
+    }
+
+    #[test]
+    fn maps_form_factors() {
+        let form_factor = |s: &str| to_form_factor(Some(s.to_string()));
+
+        assert_eq!(form_factor("speaker"), FormFactor::Speakers);
+        assert_eq!(form_factor("headphone"), FormFactor::Headphones);
+        assert_eq!(form_factor("handset"), FormFactor::Headset);
+        assert_eq!(form_factor("webcam"), FormFactor::Microphone);
+        assert_eq!(form_factor("car"), FormFactor::Unknown);
+        assert_eq!(to_form_factor(None), FormFactor::Unknown);
+    }
+
+    #[test]
+    fn send_does_not_wait_for_a_full_channel() {
+        let (tx, mut rx) = channel(1);
+        let removed = |id: &str| Notification::DeviceRemoved { id: id.to_string() };
+
+        send(&tx, removed("first"));
+        // dropped rather than blocking the mainloop
+        send(&tx, removed("second"));
+
+        assert!(matches!(rx.try_recv(), Ok(Notification::DeviceRemoved { id }) if id == "first"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    /// A null sink loaded through `pactl`, unloaded again when dropped.
+    struct NullSink {
+        module: String,
+    }
+
+    impl NullSink {
+        fn load() -> Self {
+            let output = Command::new("pactl")
+                .args([
+                    "load-module",
+                    "module-null-sink",
+                    &format!("sink_name={}", SINK),
+                    "sink_properties=device.description=OutputSwitcherTest",
+                ])
+                .output()
+                .expect("pactl could not be run");
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+
+            Self {
+                module: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            }
+        }
+    }
+
+    impl Drop for NullSink {
+        fn drop(&mut self) {
+            let _ = Command::new("pactl")
+                .args(["unload-module", &self.module])
+                .status();
+        }
+    }
+
+    /// Waits for a notification matching `f`, skipping those about other devices.
+    fn wait_for(rx: &mut Receiver<Notification>, f: impl Fn(&Notification) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            match rx.try_recv() {
+                Ok(n) if f(&n) => return,
+                Ok(_) => {}
+                Err(_) => std::thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        panic!("no matching notification within 5 seconds");
+    }
+
+    #[test]
+    #[ignore = "needs a running PulseAudio or PipeWire server and pactl"]
+    fn null_sink_is_listed_and_notifies_changes() {
+        let (tx, mut rx) = channel(256);
+        let is = Arc::new(PulseBackend::new(&tx).unwrap());
+
+        let sink = NullSink::load();
+        wait_for(
+            &mut rx,
+            |n| matches!(n, Notification::DeviceAdded { id } if id == SINK),
+        );
+
+        let device = is
+            .get_active_audio_devices()
+            .unwrap()
+            .into_iter()
+            .find(|d| d.id() == SINK)
+            .expect("the null sink is not listed");
+        assert_eq!(device.flow(), DataFlow::Render);
+        assert_eq!(device.name(), "OutputSwitcherTest");
+
+        device.set_volume(0.5).unwrap();
+        assert!((device.get_volume().unwrap() - 0.5).abs() < 0.01);
+        wait_for(&mut rx, |n| {
+            matches!(n, Notification::VolumeChanged { id, volume, muted: false }
+                if id == SINK && (volume - 0.5).abs() < 0.01)
+        });
+
+        device.set_mute_state(true).unwrap();
+        assert!(device.get_mute_state().unwrap());
+        wait_for(
+            &mut rx,
+            |n| matches!(n, Notification::VolumeChanged { id, muted: true, .. } if id == SINK),
+        );
+
+        drop(sink);
+        wait_for(
+            &mut rx,
+            |n| matches!(n, Notification::DeviceRemoved { id } if id == SINK),
+        );
+    }
+}
@@ -0,0 +1,91 @@
+#![allow(non_snake_case)]
+
+use anyhow::Result;
+use std::ffi::c_void;
+use windows::{
+    core::{interface, IInspectable, IInspectable_Vtbl, HRESULT, HSTRING},
+    Win32::{
+        Media::Audio::{eConsole, eMultimedia, eRender},
+        System::WinRT::RoGetActivationFactory,
+    },
+};
+
+// Undocumented factory behind "App volume and device preferences".
+// The IID is the one used since Windows 10 21H2; older builds used 2a59116d-6c4f-45e0-a74f-707e3fef9258.
+#[interface("ab3d4648-e242-459f-b02f-541c70306324")]
+unsafe trait IAudioPolicyConfigFactory: IInspectable {
+    fn add_CtxVolumeChange(&self) -> HRESULT;
+    fn remove_CtxVolumeChanged(&self) -> HRESULT;
+    fn add_RingerVibrateStateChanged(&self) -> HRESULT;
+    fn remove_RingerVibrateStateChange(&self) -> HRESULT;
+    fn SetVolumeGroupGainForId(&self) -> HRESULT;
+    fn GetVolumeGroupGainForId(&self) -> HRESULT;
+    fn GetActiveVolumeGroupForEndpointId(&self) -> HRESULT;
+    fn GetVolumeGroupsForEndpoint(&self) -> HRESULT;
+    fn GetCurrentVolumeContext(&self) -> HRESULT;
+    fn SetVolumeGroupMuteForId(&self) -> HRESULT;
+    fn GetVolumeGroupMuteForId(&self) -> HRESULT;
+    fn SetRingerVibrateState(&self) -> HRESULT;
+    fn GetRingerVibrateState(&self) -> HRESULT;
+    fn SetPreferredChatApplication(&self) -> HRESULT;
+    fn ResetPreferredChatApplication(&self) -> HRESULT;
+    fn GetPreferredChatApplication(&self) -> HRESULT;
+    fn GetCurrentChatApplications(&self) -> HRESULT;
+    fn add_ChatContextChanged(&self) -> HRESULT;
+    fn remove_ChatContextChanged(&self) -> HRESULT;
+    fn SetPersistedDefaultAudioEndpoint(
+        &self,
+        processId: u32,
+        flow: i32,
+        role: i32,
+        deviceId: *mut c_void,
+    ) -> HRESULT;
+    fn GetPersistedDefaultAudioEndpoint(&self) -> HRESULT;
+    fn ClearAllPersistedApplicationDefaultEndpoints(&self) -> HRESULT;
+}
+
+const AUDIO_POLICY_CONFIG_CLASS: &str = "Windows.Media.Internal.AudioPolicyConfig";
+
+// the factory expects device interface paths, not the endpoint ids of IMMDevice::GetId
+const MMDEVAPI_TOKEN: &str = r"\\?\SWD#MMDEVAPI#";
+const DEVINTERFACE_AUDIO_RENDER: &str = "#{e6327cad-dcec-4949-ae8a-991e976a79d2}";
+
+// https://github.com/File-New-Project/EarTrumpet/blob/master/EarTrumpet/DataModel/WindowsAudio/Internal/AudioPolicyConfig.cs
+
+pub(crate) struct AppRouter(IAudioPolicyConfigFactory);
+
+impl AppRouter {
+    pub(crate) fn new() -> Result<Self> {
+        let factory = unsafe { RoGetActivationFactory(&HSTRING::from(AUDIO_POLICY_CONFIG_CLASS))? };
+        Ok(AppRouter(factory))
+    }
+
+    /// Sets the render endpoint used by `process_id`, or resets it to the system default.
+    pub(crate) fn set_app_default_endpoint(
+        &self,
+        process_id: u32,
+        device_id: Option<&str>,
+    ) -> Result<()> {
+        let device_id = match device_id {
+            Some(id) => HSTRING::from(format!(
+                "{}{}{}",
+                MMDEVAPI_TOKEN, id, DEVINTERFACE_AUDIO_RENDER
+            )),
+            None => HSTRING::new(),
+        };
+
+        for role in [eConsole, eMultimedia] {
+            let hr = unsafe {
+                self.0.SetPersistedDefaultAudioEndpoint(
+                    process_id,
+                    eRender.0,
+                    role.0,
+                    std::mem::transmute_copy(&device_id),
+                )
+            };
+            hr.ok()?;
+        }
+
+        Ok(())
+    }
+}
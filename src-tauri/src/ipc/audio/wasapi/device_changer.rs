@@ -1,10 +1,10 @@
 #![allow(non_snake_case)]
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use windows::{
     core::{interface, IUnknown, IUnknown_Vtbl, GUID, HRESULT},
     Win32::{
-        Media::Audio::{eCommunications, eConsole, eMultimedia},
+        Media::Audio::ERole,
         System::Com::{CoCreateInstance, CLSCTX_ALL},
     },
 };
@@ -48,33 +48,15 @@ impl PolicyConfig {
         Ok(PolicyConfig(policy_config))
     }
 
-    pub(crate) fn set_default_endpoint(&self, device_id: &str) -> Result<()> {
+    pub(crate) fn set_default_endpoint(&self, device_id: &str, roles: &[ERole]) -> Result<()> {
         // let device_id: HSTRING = device_id.into();
         // let device_id: PCWSTR = (&device_id).into();
 
         let device_id: Vec<u16> = device_id.encode_utf16().chain(Some(0)).collect();
 
-        unsafe {
-            let hr = self
-                .0
-                .SetDefaultEndpoint(device_id.as_ptr(), eConsole.0 as _);
-            if hr.0 != 0 {
-                return Err(anyhow!("Error with 0x{:X}", hr.0));
-            }
-
-            let hr = self
-                .0
-                .SetDefaultEndpoint(device_id.as_ptr(), eMultimedia.0 as _);
-            if hr.0 != 0 {
-                return Err(anyhow!("Error with 0x{:X}", hr.0));
-            }
-
-            let hr = self
-                .0
-                .SetDefaultEndpoint(device_id.as_ptr(), eCommunications.0 as _);
-            if hr.0 != 0 {
-                return Err(anyhow!("Error with 0x{:X}", hr.0));
-            }
+        for role in roles {
+            let hr = unsafe { self.0.SetDefaultEndpoint(device_id.as_ptr(), role.0 as _) };
+            hr.ok()?;
         }
 
         Ok(())
@@ -0,0 +1,697 @@
+mod app_router;
+mod device_changer;
+mod notifier;
+
+// https://qiita.com/benki/items/635867b654783da0322f
+
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    ffi::{c_void, OsString},
+    os::windows::ffi::OsStringExt,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc::Sender;
+use windows::{
+    core::Interface,
+    Win32::{
+        Devices::FunctionDiscovery::PKEY_Device_FriendlyName,
+        Foundation::{CloseHandle, FALSE},
+        Media::Audio::{
+            eAll, eCapture, eCommunications, eConsole, eMultimedia, eRender,
+            AudioSessionStateExpired, EDataFlow, ERole, EndpointFormFactor,
+            Endpoints::{
+                IAudioEndpointVolume, IAudioEndpointVolumeCallback, IAudioMeterInformation,
+            },
+            Headphones, Headset, IAudioSessionControl, IAudioSessionControl2,
+            IAudioSessionEnumerator, IAudioSessionEvents, IAudioSessionManager2,
+            IAudioSessionNotification, IMMDevice, IMMDeviceEnumerator, IMMEndpoint,
+            ISimpleAudioVolume, MMDeviceEnumerator, Microphone, PKEY_AudioEndpoint_FormFactor,
+            Speakers, DEVICE_STATE_ACTIVE,
+        },
+        System::{
+            Com::{
+                CoCreateInstance, CoInitialize, CoTaskMemFree, CoUninitialize,
+                StructuredStorage::{PropVariantToStringAlloc, PropVariantToUInt32},
+                CLSCTX_ALL, STGM_READ,
+            },
+            ProcessStatus::GetModuleBaseNameW,
+            Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
+        },
+    },
+};
+
+use super::{
+    notifier::Notification, AudioBackend, AudioEndpoint, AudioSession, DataFlow, FormFactor,
+    PeakLevels, Role, VolumeRange,
+};
+
+impl From<DataFlow> for EDataFlow {
+    fn from(flow: DataFlow) -> Self {
+        match flow {
+            DataFlow::Render => eRender,
+            DataFlow::Capture => eCapture,
+        }
+    }
+}
+
+pub(crate) fn to_data_flow(flow: EDataFlow) -> DataFlow {
+    if flow == eCapture {
+        DataFlow::Capture
+    } else {
+        DataFlow::Render
+    }
+}
+
+impl From<Role> for ERole {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::Console => eConsole,
+            Role::Multimedia => eMultimedia,
+            Role::Communications => eCommunications,
+        }
+    }
+}
+
+pub(crate) fn to_role(role: ERole) -> Role {
+    if role == eConsole {
+        Role::Console
+    } else if role == eCommunications {
+        Role::Communications
+    } else {
+        Role::Multimedia
+    }
+}
+
+struct Com;
+
+impl Com {
+    pub fn new() -> Result<Self> {
+        unsafe {
+            let _ = CoInitialize(None);
+        }
+
+        Ok(Com)
+    }
+}
+
+impl Drop for Com {
+    fn drop(&mut self) {
+        unsafe {
+            CoUninitialize();
+        }
+    }
+}
+
+pub struct Singleton {
+    _com: Com,
+
+    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/mmdeviceapi/nn-mmdeviceapi-immdeviceenumerator
+    pub(crate) device_enumerator: IMMDeviceEnumerator,
+    notification_callbacks: notifier::NotificationCallbacks,
+    policy_config: device_changer::PolicyConfig,
+
+    /// `None` on Windows builds without the per-application device preferences.
+    app_router: Option<app_router::AppRouter>,
+}
+
+unsafe impl Send for Singleton {}
+unsafe impl Sync for Singleton {}
+
+impl AudioBackend for Singleton {
+    type Endpoint = IMMAudioDevice;
+
+    fn new(tx: &Sender<Notification>) -> Result<Self> {
+        let com = Com::new()?;
+        let device_enumerator = unsafe { CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)? };
+        let notification_callbacks = notifier::NotificationCallbacks::new(tx);
+        notification_callbacks.register_to_enumerator(&device_enumerator)?;
+
+        let policy_config = device_changer::PolicyConfig::new()?;
+
+        let app_router = app_router::AppRouter::new()
+            .map_err(|e| log::warn!("AppRouter::new {:?}", e))
+            .ok();
+
+        Ok(Singleton {
+            _com: com,
+            device_enumerator,
+            notification_callbacks,
+            policy_config,
+            app_router,
+        })
+    }
+
+    fn get_active_audio_devices(self: &Arc<Self>) -> Result<Vec<IMMAudioDevice>> {
+        // https://learn.microsoft.com/ja-jp/windows/win32/api/mmdeviceapi/nn-mmdeviceapi-immdevicecollection
+        let device_collection = unsafe {
+            self.device_enumerator
+                .EnumAudioEndpoints(eAll, DEVICE_STATE_ACTIVE)?
+        };
+
+        let len = unsafe { device_collection.GetCount()? };
+
+        let devices = (0..len)
+            .map(|i| {
+                let device = unsafe { device_collection.Item(i)? };
+                IMMAudioDevice::new(Arc::clone(self), device)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(devices)
+    }
+
+    fn get_default_audio_id(&self, flow: DataFlow, role: Role) -> Result<String> {
+        let device = unsafe {
+            self.device_enumerator
+                .GetDefaultAudioEndpoint(flow.into(), role.into())?
+        };
+        let id = unsafe { device.GetId()?.to_string()? };
+
+        Ok(id)
+    }
+
+    fn set_app_default_device(&self, process_id: u32, device_id: Option<&str>) -> Result<()> {
+        let app_router = self
+            .app_router
+            .as_ref()
+            .ok_or_else(|| anyhow!("Per-application routing is not available on this system"))?;
+
+        app_router.set_app_default_endpoint(process_id, device_id)
+    }
+}
+
+impl Drop for Singleton {
+    fn drop(&mut self) {
+        let e = self
+            .notification_callbacks
+            .unregister_to_enumerator(&self.device_enumerator);
+        if let Err(e) = e {
+            log::warn!("unregister_to_enumerator {:?}", e);
+        }
+    }
+}
+
+fn get_name_from_immdevice(device: &IMMDevice) -> Result<String> {
+    let property_store = unsafe { device.OpenPropertyStore(STGM_READ)? };
+    let name_propvariant = unsafe { property_store.GetValue(&PKEY_Device_FriendlyName)? };
+    let name = unsafe { PropVariantToStringAlloc(&name_propvariant)?.to_string()? };
+
+    Ok(name)
+}
+
+// https://learn.microsoft.com/ja-jp/windows/win32/coreaudio/pkey-audioendpoint-formfactor
+fn get_form_factor_from_immdevice(device: &IMMDevice) -> Result<FormFactor> {
+    let property_store = unsafe { device.OpenPropertyStore(STGM_READ)? };
+    let propvariant = unsafe { property_store.GetValue(&PKEY_AudioEndpoint_FormFactor)? };
+    let form_factor = EndpointFormFactor(unsafe { PropVariantToUInt32(&propvariant)? } as i32);
+
+    let form_factor = match form_factor {
+        Speakers => FormFactor::Speakers,
+        Headphones => FormFactor::Headphones,
+        Headset => FormFactor::Headset,
+        Microphone => FormFactor::Microphone,
+        _ => FormFactor::Unknown,
+    };
+
+    Ok(form_factor)
+}
+
+pub struct IMMAudioDevice {
+    is: Arc<Singleton>,
+
+    pub id: String,
+    pub name: String,
+    pub flow: DataFlow,
+    pub form_factor: FormFactor,
+
+    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/mmdeviceapi/nn-mmdeviceapi-immdevice
+    _device: IMMDevice,
+
+    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/endpointvolume/nn-endpointvolume-iaudioendpointvolume
+    pub(crate) endpoint_volume: IAudioEndpointVolume,
+
+    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/endpointvolume/nn-endpointvolume-iaudioendpointvolumecallback
+    volume_callback: IAudioEndpointVolumeCallback,
+
+    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/endpointvolume/nn-endpointvolume-iaudiometerinformation
+    meter: IAudioMeterInformation,
+
+    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/audiopolicy/nn-audiopolicy-iaudiosessionmanager2
+    pub(crate) session_manager: IAudioSessionManager2,
+
+    /// @see https://learn.microsoft.com/ja-jp/windows/win32/api/audiopolicy/nn-audiopolicy-iaudiosessionnotification
+    session_notification: IAudioSessionNotification,
+
+    /// Keyed by session instance id, as one process may have several sessions.
+    pub(crate) session_control_map: Mutex<HashMap<String, SessionEntry>>,
+}
+
+/// A session together with the callback registered to it.
+pub(crate) struct SessionEntry {
+    control: IAudioSessionControl,
+    process_id: u32,
+    events: IAudioSessionEvents,
+}
+
+unsafe impl Send for IMMAudioDevice {}
+unsafe impl Sync for IMMAudioDevice {}
+
+impl IMMAudioDevice {
+    pub fn new(is: Arc<Singleton>, device: IMMDevice) -> Result<Self> {
+        let id = unsafe { device.GetId()?.to_string()? };
+        let name = get_name_from_immdevice(&device)?;
+
+        // https://learn.microsoft.com/ja-jp/windows/win32/api/mmdeviceapi/nn-mmdeviceapi-immendpoint
+        let endpoint: IMMEndpoint = device.cast()?;
+        let flow = to_data_flow(unsafe { endpoint.GetDataFlow()? });
+        let form_factor = get_form_factor_from_immdevice(&device).unwrap_or_default();
+
+        // https://learn.microsoft.com/ja-jp/windows/win32/api/mmdeviceapi/nf-mmdeviceapi-immdevice-activate
+        // https://learn.microsoft.com/ja-jp/windows/win32/api/endpointvolume/nn-endpointvolume-iaudioendpointvolume
+        let endpoint_volume: IAudioEndpointVolume = unsafe { device.Activate(CLSCTX_ALL, None)? };
+
+        let meter: IAudioMeterInformation = unsafe { device.Activate(CLSCTX_ALL, None)? };
+
+        // https://learn.microsoft.com/ja-jp/windows/win32/api/audiopolicy/nn-audiopolicy-iaudiosessionmanager2
+        let session_manager: IAudioSessionManager2 = unsafe { device.Activate(CLSCTX_ALL, None)? };
+
+        // sessions created before the first GetSessionEnumerator call are never notified
+        let _ = unsafe { session_manager.GetSessionEnumerator()? };
+        let session_notification = is
+            .notification_callbacks
+            .register_to_session_manager(&id, &session_manager)?;
+
+        let volume_callback = is
+            .notification_callbacks
+            .register_to_volume(&id, &endpoint_volume)?;
+
+        Ok(IMMAudioDevice {
+            id,
+            name,
+            flow,
+            form_factor,
+            _device: device,
+            endpoint_volume,
+            volume_callback,
+            meter,
+            is,
+            session_manager,
+            session_notification,
+            session_control_map: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Re-enumerates the sessions of this device, since applications come and go at any time.
+    ///
+    /// Sessions seen for the first time get an `IAudioSessionEvents` callback, and callbacks of
+    /// sessions which are gone are unregistered. A session which fails to be read is left out
+    /// rather than failing the whole device.
+    pub(crate) fn refresh_sessions(&self) -> Result<Vec<u32>> {
+        let mut session_control_map = self
+            .session_control_map
+            .lock()
+            .map_err(|_| anyhow!("session_control_map is poisoned"))?;
+
+        let mut current = HashMap::new();
+
+        let sessions = unsafe { self.session_manager.GetSessionEnumerator()? };
+        for i in 0..unsafe { sessions.GetCount()? } {
+            match self.refresh_session(&sessions, i, &mut session_control_map) {
+                Ok(Some((instance_id, entry))) => {
+                    current.insert(instance_id, entry);
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("@refresh_session {:?}", e),
+            }
+        }
+
+        for (_, stale) in session_control_map.drain() {
+            self.unregister_session(&stale);
+        }
+        *session_control_map = current;
+
+        let mut process_ids: Vec<u32> = session_control_map
+            .values()
+            .map(|entry| entry.process_id)
+            .collect();
+        process_ids.sort();
+        process_ids.dedup();
+
+        Ok(process_ids)
+    }
+
+    /// Takes the entry of the `index`th session out of `known`, or registers a callback to it
+    /// when it is new. `None` for expired sessions.
+    fn refresh_session(
+        &self,
+        sessions: &IAudioSessionEnumerator,
+        index: i32,
+        known: &mut HashMap<String, SessionEntry>,
+    ) -> Result<Option<(String, SessionEntry)>> {
+        let session_control: IAudioSessionControl = unsafe { sessions.GetSession(index)? };
+        if unsafe { session_control.GetState()? } == AudioSessionStateExpired {
+            return Ok(None);
+        }
+
+        let session_control2: IAudioSessionControl2 = session_control.cast()?;
+        let process_id = unsafe { session_control2.GetProcessId()? };
+        let instance_id = get_session_instance_id(&session_control2)?;
+
+        if let Some(entry) = known.remove(&instance_id) {
+            return Ok(Some((instance_id, entry)));
+        }
+
+        let events = self.is.notification_callbacks.register_to_session(
+            &self.id,
+            process_id,
+            &session_control,
+        )?;
+
+        let entry = SessionEntry {
+            control: session_control,
+            process_id,
+            events,
+        };
+
+        Ok(Some((instance_id, entry)))
+    }
+
+    fn unregister_session(&self, entry: &SessionEntry) {
+        let e = self
+            .is
+            .notification_callbacks
+            .unregister_to_session(&entry.control, &entry.events);
+
+        if let Err(e) = e {
+            log::warn!("unregister_to_session {:?}", e);
+        }
+    }
+
+    pub(crate) fn get_session(&self, process_id: u32) -> Result<IAudioSessionControl> {
+        let lookup = || -> Result<Option<IAudioSessionControl>> {
+            let map = self
+                .session_control_map
+                .lock()
+                .map_err(|_| anyhow!("session_control_map is poisoned"))?;
+            Ok(map
+                .values()
+                .find(|entry| entry.process_id == process_id)
+                .map(|entry| entry.control.clone()))
+        };
+
+        if let Some(session_control) = lookup()? {
+            return Ok(session_control);
+        }
+
+        self.refresh_sessions()?;
+        lookup()?.ok_or_else(|| anyhow!("No such session: {}", process_id))
+    }
+
+    pub(crate) fn get_session_audio_volume(&self, process_id: u32) -> Result<ISimpleAudioVolume> {
+        let session_control = self.get_session(process_id)?;
+        let audio_volume: ISimpleAudioVolume = session_control.cast()?;
+        Ok(audio_volume)
+    }
+
+    /// Applies `f` to every session of `process_id`, as browsers and games often open several.
+    ///
+    /// A session which fails is skipped, so this only fails when none could be changed.
+    fn for_each_session<F>(&self, process_id: u32, f: F) -> Result<()>
+    where
+        F: Fn(&ISimpleAudioVolume) -> windows::core::Result<()>,
+    {
+        // sessions opened since the last notification are changed as well
+        self.refresh_sessions()?;
+        let controls: Vec<IAudioSessionControl> = self
+            .session_control_map
+            .lock()
+            .map_err(|_| anyhow!("session_control_map is poisoned"))?
+            .values()
+            .filter(|entry| entry.process_id == process_id)
+            .map(|entry| entry.control.clone())
+            .collect();
+
+        let mut result = Err(anyhow!("No such session: {}", process_id));
+        for control in controls {
+            match control.cast::<ISimpleAudioVolume>().and_then(|v| f(&v)) {
+                Ok(()) => result = Ok(()),
+                Err(e) => {
+                    log::warn!("@session {} {:?}", process_id, e);
+                    if result.is_err() {
+                        result = Err(e.into());
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn get_session_volume(&self, process_id: u32) -> Result<f32> {
+        let audio_volume = self.get_session_audio_volume(process_id)?;
+        let volume = unsafe { audio_volume.GetMasterVolume()? };
+
+        Ok(volume)
+    }
+
+    pub fn get_session_mute_state(&self, process_id: u32) -> Result<bool> {
+        let audio_volume = self.get_session_audio_volume(process_id)?;
+        let mute_state = unsafe { audio_volume.GetMute()?.as_bool() };
+
+        Ok(mute_state)
+    }
+}
+
+impl AudioEndpoint for IMMAudioDevice {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn flow(&self) -> DataFlow {
+        self.flow
+    }
+
+    fn form_factor(&self) -> FormFactor {
+        self.form_factor
+    }
+
+    fn set_as_default(&self, roles: &[Role]) -> Result<()> {
+        let roles = roles.iter().map(|r| (*r).into()).collect::<Vec<ERole>>();
+        self.is
+            .policy_config
+            .set_default_endpoint(&self.id, &roles)?;
+
+        Ok(())
+    }
+
+    fn get_volume(&self) -> Result<f32> {
+        let volume = unsafe { self.endpoint_volume.GetMasterVolumeLevelScalar()? };
+
+        Ok(volume)
+    }
+
+    fn set_volume(&self, volume: f32) -> Result<()> {
+        unsafe {
+            self.endpoint_volume
+                .SetMasterVolumeLevelScalar(volume, std::ptr::null())?;
+        }
+
+        Ok(())
+    }
+
+    fn get_volume_db(&self) -> Result<f32> {
+        let db = unsafe { self.endpoint_volume.GetMasterVolumeLevel()? };
+
+        Ok(db)
+    }
+
+    fn set_volume_db(&self, db: f32) -> Result<()> {
+        unsafe {
+            self.endpoint_volume
+                .SetMasterVolumeLevel(db, std::ptr::null())?;
+        }
+
+        Ok(())
+    }
+
+    /// Windows does not tell how the scalar maps to dB, so the dB is read back after setting
+    /// `volume`.
+    fn volume_to_db(&self, volume: f32) -> Result<f32> {
+        unsafe {
+            let previous = self.endpoint_volume.GetMasterVolumeLevel()?;
+            self.endpoint_volume
+                .SetMasterVolumeLevelScalar(volume, std::ptr::null())?;
+            let db = self.endpoint_volume.GetMasterVolumeLevel();
+            self.endpoint_volume
+                .SetMasterVolumeLevel(previous, std::ptr::null())?;
+
+            Ok(db?)
+        }
+    }
+
+    fn get_volume_range(&self) -> Result<VolumeRange> {
+        let mut range = VolumeRange {
+            min_db: 0.0,
+            max_db: 0.0,
+            increment_db: 0.0,
+        };
+        unsafe {
+            self.endpoint_volume.GetVolumeRange(
+                &mut range.min_db,
+                &mut range.max_db,
+                &mut range.increment_db,
+            )?;
+        }
+
+        Ok(range)
+    }
+
+    fn volume_step(&self, up: bool) -> Result<()> {
+        unsafe {
+            if up {
+                self.endpoint_volume.VolumeStepUp(std::ptr::null())?;
+            } else {
+                self.endpoint_volume.VolumeStepDown(std::ptr::null())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_peak_levels(&self) -> Result<PeakLevels> {
+        unsafe {
+            let peak = self.meter.GetPeakValue()?;
+            let mut channels = vec![0.0; self.meter.GetMeteringChannelCount()? as usize];
+            self.meter.GetChannelsPeakValues(&mut channels)?;
+
+            Ok(PeakLevels { peak, channels })
+        }
+    }
+
+    fn get_mute_state(&self) -> Result<bool> {
+        let mute_state = unsafe { self.endpoint_volume.GetMute()?.as_bool() };
+
+        Ok(mute_state)
+    }
+
+    fn set_mute_state(&self, mute_state: bool) -> Result<()> {
+        unsafe {
+            self.endpoint_volume.SetMute(mute_state, std::ptr::null())?;
+        }
+
+        Ok(())
+    }
+
+    fn get_sessions(&self) -> Result<Vec<AudioSession>> {
+        let sessions = self
+            .refresh_sessions()?
+            .into_iter()
+            .filter_map(|process_id| {
+                let session = || -> Result<AudioSession> {
+                    Ok(AudioSession {
+                        pid: process_id,
+                        name: get_session_name(process_id),
+                        volume: self.get_session_volume(process_id)?,
+                        muted: self.get_session_mute_state(process_id)?,
+                    })
+                };
+
+                // the process may exit between enumerating and reading its session
+                session()
+                    .map_err(|e| log::warn!("@get_session {} {:?}", process_id, e))
+                    .ok()
+            })
+            .collect();
+
+        Ok(sessions)
+    }
+
+    fn set_session_volume(&self, process_id: u32, volume: f32) -> Result<()> {
+        self.for_each_session(process_id, |audio_volume| unsafe {
+            audio_volume.SetMasterVolume(volume, std::ptr::null())
+        })
+    }
+
+    fn set_session_mute_state(&self, process_id: u32, mute_state: bool) -> Result<()> {
+        self.for_each_session(process_id, |audio_volume| unsafe {
+            audio_volume.SetMute(mute_state, std::ptr::null())
+        })
+    }
+}
+
+impl Drop for IMMAudioDevice {
+    fn drop(&mut self) {
+        let entries: Vec<SessionEntry> = match self.session_control_map.get_mut() {
+            Ok(session_control_map) => session_control_map.drain().map(|(_, e)| e).collect(),
+            Err(_) => Vec::new(),
+        };
+        for entry in entries {
+            self.unregister_session(&entry);
+        }
+
+        // fails once the device is unplugged, which is when most devices are dropped
+        let e = self
+            .is
+            .notification_callbacks
+            .unregister_to_session_manager(&self.session_manager, &self.session_notification);
+        if let Err(e) = e {
+            log::warn!("unregister_to_session_manager {:?}", e);
+        }
+
+        let e = self
+            .is
+            .notification_callbacks
+            .unregister_to_volume(&self.endpoint_volume, &self.volume_callback);
+        if let Err(e) = e {
+            log::warn!("unregister_to_volume {:?}", e);
+        }
+    }
+}
+
+// https://learn.microsoft.com/ja-jp/windows/win32/api/audiopolicy/nf-audiopolicy-iaudiosessioncontrol2-getsessioninstanceidentifier
+fn get_session_instance_id(session_control2: &IAudioSessionControl2) -> Result<String> {
+    unsafe {
+        let id = session_control2.GetSessionInstanceIdentifier()?;
+        let instance_id = id.to_string();
+        CoTaskMemFree(Some(id.0 as *const c_void));
+
+        Ok(instance_id?)
+    }
+}
+
+fn get_session_name(process_id: u32) -> String {
+    if process_id == 0 {
+        return "System Sounds".to_string();
+    }
+
+    unsafe { get_process_name_by_id(process_id) }.unwrap_or_else(|_| "Unknown".to_string())
+}
+
+unsafe fn get_process_name_by_id(process_id: u32) -> Result<String> {
+    let try_process_handle = OpenProcess(
+        PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
+        FALSE,
+        process_id,
+    );
+
+    if let Err(e) = try_process_handle {
+        return Err(anyhow::anyhow!("Failed to open process: {}", e));
+    }
+
+    let process_handle = try_process_handle.unwrap();
+
+    let mut buffer = [0; 1024];
+    let len = GetModuleBaseNameW(process_handle, None, &mut buffer);
+
+    let os_string = OsString::from_wide(&buffer[..len as usize]);
+    let process_name = os_string.to_string_lossy().into_owned();
+
+    CloseHandle(process_handle).unwrap();
+
+    Ok(process_name)
+}
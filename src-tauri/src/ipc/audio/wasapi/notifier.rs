@@ -0,0 +1,405 @@
+use super::super::notifier::Notification;
+use super::{to_data_flow, to_role};
+use anyhow::Result;
+use std::fmt::Debug;
+use tokio::sync::mpsc::Sender;
+use windows::{
+    core::{implement, Interface, GUID, PCWSTR},
+    Win32::{
+        Foundation::{BOOL, ERROR_ACCESS_DENIED, ERROR_INVALID_DATA, WIN32_ERROR},
+        Media::Audio::{
+            AudioSessionDisconnectReason, AudioSessionState, EDataFlow, ERole,
+            Endpoints::{
+                IAudioEndpointVolume, IAudioEndpointVolumeCallback,
+                IAudioEndpointVolumeCallback_Impl,
+            },
+            IAudioSessionControl, IAudioSessionControl2, IAudioSessionEvents,
+            IAudioSessionEvents_Impl, IAudioSessionManager2, IAudioSessionNotification,
+            IAudioSessionNotification_Impl, IMMDeviceEnumerator, IMMNotificationClient,
+            IMMNotificationClient_Impl, AUDIO_VOLUME_NOTIFICATION_DATA, DEVICE_STATE,
+        },
+        UI::Shell::PropertiesSystem::PROPERTYKEY,
+    },
+};
+
+fn to_win_error<E: Debug>(e: E, code: WIN32_ERROR) -> windows::core::Error {
+    windows::core::Error::new::<String>(code.to_hresult(), format!("{:?}", e).into())
+}
+
+#[implement(IMMNotificationClient)]
+struct AppEventHandlerClient(Sender<Notification>);
+
+impl IMMNotificationClient_Impl for AppEventHandlerClient {
+    fn OnDeviceStateChanged(
+        &self,
+        pwstrdeviceid: &PCWSTR,
+        dwnewstate: DEVICE_STATE,
+    ) -> windows::core::Result<()> {
+        unsafe {
+            self.0
+                .blocking_send(Notification::DeviceStateChanged {
+                    // .send(Notification::DeviceStateChanged {
+                    id: pwstrdeviceid
+                        .to_string()
+                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
+                    state: dwnewstate.0,
+                })
+                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+        }
+
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, pwstrdeviceid: &PCWSTR) -> windows::core::Result<()> {
+        unsafe {
+            self.0
+                .blocking_send(Notification::DeviceAdded {
+                    // .send(Notification::DeviceAdded {
+                    id: pwstrdeviceid
+                        .to_string()
+                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
+                })
+                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+        }
+
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, pwstrdeviceid: &PCWSTR) -> windows::core::Result<()> {
+        unsafe {
+            self.0
+                .blocking_send(Notification::DeviceRemoved {
+                    // .send(Notification::DeviceRemoved {
+                    id: pwstrdeviceid
+                        .to_string()
+                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
+                })
+                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+        }
+
+        Ok(())
+    }
+
+    fn OnDefaultDeviceChanged(
+        &self,
+        flow: EDataFlow,
+        role: ERole,
+        pwstrdefaultdeviceid: &PCWSTR,
+    ) -> windows::core::Result<()> {
+        unsafe {
+            self.0
+                .blocking_send(Notification::DefaultDeviceChanged {
+                    // .send(Notification::DefaultDeviceChanged {
+                    id: pwstrdefaultdeviceid
+                        .to_string()
+                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
+                    flow: to_data_flow(flow),
+                    role: to_role(role),
+                })
+                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+        }
+
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(
+        &self,
+        pwstrdeviceid: &PCWSTR,
+        key: &PROPERTYKEY,
+    ) -> windows::core::Result<()> {
+        unsafe {
+            self.0
+                .blocking_send(Notification::PropertyValueChanged {
+                    // .send(Notification::PropertyValueChanged {
+                    id: pwstrdeviceid
+                        .to_string()
+                        .map_err(|e| to_win_error(e, ERROR_INVALID_DATA))?,
+                    key: format!("{:?}", key.fmtid),
+                })
+                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The notification data only carries the event context, so each device gets its own callback
+/// which knows the device id.
+#[implement(IAudioEndpointVolumeCallback)]
+struct AudioEndpointVolumeCallback {
+    tx: Sender<Notification>,
+    device_id: String,
+}
+
+impl IAudioEndpointVolumeCallback_Impl for AudioEndpointVolumeCallback {
+    fn OnNotify(&self, data: *mut AUDIO_VOLUME_NOTIFICATION_DATA) -> windows::core::Result<()> {
+        unsafe {
+            if data == std::ptr::null_mut() {
+                return Err(to_win_error("data is null", ERROR_INVALID_DATA));
+            }
+
+            self.tx
+                .blocking_send(Notification::VolumeChanged {
+                    // .send(Notification::VolumeChanged {
+                    id: self.device_id.clone(),
+                    volume: (*data).fMasterVolume,
+                    muted: (*data).bMuted.as_bool(),
+                })
+                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// @see https://learn.microsoft.com/ja-jp/windows/win32/api/audiopolicy/nn-audiopolicy-iaudiosessionnotification
+#[implement(IAudioSessionNotification)]
+struct AudioSessionNotificationCallback {
+    tx: Sender<Notification>,
+    device_id: String,
+}
+
+impl IAudioSessionNotification_Impl for AudioSessionNotificationCallback {
+    fn OnSessionCreated(
+        &self,
+        newsession: Option<&IAudioSessionControl>,
+    ) -> windows::core::Result<()> {
+        let Some(session_control) = newsession else {
+            return Err(to_win_error("newsession is null", ERROR_INVALID_DATA));
+        };
+
+        unsafe {
+            let session_control2: IAudioSessionControl2 = session_control.cast()?;
+
+            self.tx
+                .blocking_send(Notification::SessionCreated {
+                    id: self.device_id.clone(),
+                    pid: session_control2.GetProcessId()?,
+                })
+                .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// @see https://learn.microsoft.com/ja-jp/windows/win32/api/audiopolicy/nn-audiopolicy-iaudiosessionevents
+#[implement(IAudioSessionEvents)]
+struct AudioSessionEventsCallback {
+    tx: Sender<Notification>,
+    device_id: String,
+    process_id: u32,
+}
+
+impl IAudioSessionEvents_Impl for AudioSessionEventsCallback {
+    fn OnDisplayNameChanged(
+        &self,
+        _newdisplayname: &PCWSTR,
+        _eventcontext: *const GUID,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnIconPathChanged(
+        &self,
+        _newiconpath: &PCWSTR,
+        _eventcontext: *const GUID,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnSimpleVolumeChanged(
+        &self,
+        newvolume: f32,
+        newmute: BOOL,
+        _eventcontext: *const GUID,
+    ) -> windows::core::Result<()> {
+        self.tx
+            .blocking_send(Notification::SessionVolumeChanged {
+                id: self.device_id.clone(),
+                pid: self.process_id,
+                volume: newvolume,
+                muted: newmute.as_bool(),
+            })
+            .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+
+        Ok(())
+    }
+
+    fn OnChannelVolumeChanged(
+        &self,
+        _channelcount: u32,
+        _newchannelvolumearray: *const f32,
+        _changedchannel: u32,
+        _eventcontext: *const GUID,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnGroupingParamChanged(
+        &self,
+        _newgroupingparam: *const GUID,
+        _eventcontext: *const GUID,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnStateChanged(&self, newstate: AudioSessionState) -> windows::core::Result<()> {
+        self.tx
+            .blocking_send(Notification::SessionStateChanged {
+                id: self.device_id.clone(),
+                pid: self.process_id,
+                state: newstate.0 as u32,
+            })
+            .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+
+        Ok(())
+    }
+
+    fn OnSessionDisconnected(
+        &self,
+        disconnectreason: AudioSessionDisconnectReason,
+    ) -> windows::core::Result<()> {
+        self.tx
+            .blocking_send(Notification::SessionDisconnected {
+                id: self.device_id.clone(),
+                pid: self.process_id,
+                reason: disconnectreason.0 as u32,
+            })
+            .map_err(|e| to_win_error(e, ERROR_ACCESS_DENIED))?;
+
+        Ok(())
+    }
+}
+
+pub(crate) struct NotificationCallbacks {
+    tx: Sender<Notification>,
+    notification_client: IMMNotificationClient,
+}
+
+impl NotificationCallbacks {
+    pub(crate) fn new(tx: &Sender<Notification>) -> Self {
+        let notification_client = AppEventHandlerClient(tx.clone()).into();
+
+        Self {
+            tx: tx.clone(),
+            notification_client,
+        }
+    }
+
+    pub(crate) fn register_to_enumerator(
+        &self,
+        device_enumerator: &IMMDeviceEnumerator,
+    ) -> Result<()> {
+        unsafe {
+            device_enumerator.RegisterEndpointNotificationCallback(&self.notification_client)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn unregister_to_enumerator(
+        &self,
+        device_enumerator: &IMMDeviceEnumerator,
+    ) -> Result<()> {
+        unsafe {
+            device_enumerator.UnregisterEndpointNotificationCallback(&self.notification_client)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn register_to_volume(
+        &self,
+        device_id: &str,
+        volume: &IAudioEndpointVolume,
+    ) -> Result<IAudioEndpointVolumeCallback> {
+        let volume_callback: IAudioEndpointVolumeCallback = AudioEndpointVolumeCallback {
+            tx: self.tx.clone(),
+            device_id: device_id.to_string(),
+        }
+        .into();
+
+        unsafe {
+            volume.RegisterControlChangeNotify(&volume_callback)?;
+        }
+
+        Ok(volume_callback)
+    }
+
+    pub(crate) fn unregister_to_volume(
+        &self,
+        volume: &IAudioEndpointVolume,
+        volume_callback: &IAudioEndpointVolumeCallback,
+    ) -> Result<()> {
+        unsafe {
+            volume.UnregisterControlChangeNotify(volume_callback)?;
+        }
+
+        Ok(())
+    }
+
+    /// Session callbacks carry the device and process they belong to, so each registration
+    /// gets its own callback object which has to be handed back to unregister.
+    pub(crate) fn register_to_session_manager(
+        &self,
+        device_id: &str,
+        session_manager: &IAudioSessionManager2,
+    ) -> Result<IAudioSessionNotification> {
+        let session_notification: IAudioSessionNotification = AudioSessionNotificationCallback {
+            tx: self.tx.clone(),
+            device_id: device_id.to_string(),
+        }
+        .into();
+
+        unsafe {
+            session_manager.RegisterSessionNotification(&session_notification)?;
+        }
+
+        Ok(session_notification)
+    }
+
+    pub(crate) fn unregister_to_session_manager(
+        &self,
+        session_manager: &IAudioSessionManager2,
+        session_notification: &IAudioSessionNotification,
+    ) -> Result<()> {
+        unsafe {
+            session_manager.UnregisterSessionNotification(session_notification)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn register_to_session(
+        &self,
+        device_id: &str,
+        process_id: u32,
+        session_control: &IAudioSessionControl,
+    ) -> Result<IAudioSessionEvents> {
+        let session_events: IAudioSessionEvents = AudioSessionEventsCallback {
+            tx: self.tx.clone(),
+            device_id: device_id.to_string(),
+            process_id,
+        }
+        .into();
+
+        unsafe {
+            session_control.RegisterAudioSessionNotification(&session_events)?;
+        }
+
+        Ok(session_events)
+    }
+
+    pub(crate) fn unregister_to_session(
+        &self,
+        session_control: &IAudioSessionControl,
+        session_events: &IAudioSessionEvents,
+    ) -> Result<()> {
+        unsafe {
+            session_control.UnregisterAudioSessionNotification(session_events)?;
+        }
+
+        Ok(())
+    }
+}
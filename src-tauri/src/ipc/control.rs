@@ -0,0 +1,132 @@
+use anyhow::{anyhow, Result};
+
+use super::{
+    audio::{AudioBackend, AudioEndpoint, DataFlow, Role},
+    error::APIError,
+    preferences::DevicePreferences,
+    sender::AudioDeviceMap,
+};
+
+/// Finds a device by its id, or else by its name ignoring case.
+pub fn find_device<'a, E: AudioEndpoint>(
+    audio_dict: &'a AudioDeviceMap<E>,
+    device: &str,
+) -> Result<&'a E> {
+    if let Some(audio) = audio_dict.get(device) {
+        return Ok(audio);
+    }
+
+    audio_dict
+        .values()
+        .find(|a| a.name().eq_ignore_ascii_case(device))
+        .ok_or_else(|| {
+            APIError::DeviceNotFound {
+                id: device.to_string(),
+            }
+            .into()
+        })
+}
+
+/// The device named by `device`, or the default output when it is `None`.
+pub fn target_device<'a, B: AudioBackend>(
+    is: &B,
+    audio_dict: &'a AudioDeviceMap<B::Endpoint>,
+    device: Option<&str>,
+) -> Result<&'a B::Endpoint> {
+    match device {
+        Some(device) => find_device(audio_dict, device),
+        None => {
+            let id = is.get_default_audio_id(DataFlow::Render, Role::Multimedia)?;
+            find_device(audio_dict, &id)
+        }
+    }
+}
+
+/// The output after the default one, wrapping around to the first.
+///
+/// Cycles through the outputs the window lists, in the same order, skipping hidden ones.
+pub fn next_device<'a, B: AudioBackend>(
+    is: &B,
+    audio_dict: &'a AudioDeviceMap<B::Endpoint>,
+    preferences: &DevicePreferences,
+) -> Result<&'a B::Endpoint> {
+    let default = is.get_default_audio_id(DataFlow::Render, Role::Multimedia)?;
+    let mut outputs = audio_dict
+        .values()
+        .filter(|a| a.flow() == DataFlow::Render && !preferences.is_hidden(a.id(), a.name()))
+        .collect::<Vec<_>>();
+    outputs.sort_by_key(|a| preferences.order(a.id()));
+
+    let next = outputs
+        .iter()
+        .position(|a| a.id() == default)
+        .map_or(0, |i| (i + 1) % outputs.len());
+
+    outputs
+        .get(next)
+        .copied()
+        .ok_or_else(|| anyhow!("No output device"))
+}
+
+/// Makes `audio` the default device for every role.
+pub fn switch_device<E: AudioEndpoint>(audio: &E) -> Result<()> {
+    audio.set_as_default(&Role::ALL)
+}
+
+/// Moves the volume by `step` (`0.05` is 5%), up to `max_volume`, and returns the new volume.
+pub fn step_volume<E: AudioEndpoint>(audio: &E, step: f32, max_volume: f32) -> Result<f32> {
+    let volume = (audio.get_volume()? + step).clamp(0.0, max_volume);
+    audio.set_volume(volume)?;
+
+    Ok(volume)
+}
+
+/// Moves the volume one step of the device's own volume control, up to `max_volume`, and
+/// returns the new volume.
+pub fn hardware_step<E: AudioEndpoint>(audio: &E, up: bool, max_volume: f32) -> Result<f32> {
+    if up && audio.get_volume()? >= max_volume {
+        return Ok(max_volume);
+    }
+
+    audio.volume_step(up)?;
+    pull_back(audio, max_volume)
+}
+
+/// Sets the volume in dB within the device's range, keeping it under `max_volume`, and returns
+/// the resulting volume as a scalar.
+pub fn set_volume_db<E: AudioEndpoint>(audio: &E, db: f32, max_volume: f32) -> Result<f32> {
+    let mut db = match audio.get_volume_range() {
+        Ok(range) => db.clamp(range.min_db, range.max_db),
+        Err(_) => db,
+    };
+
+    // lowering the volume cannot go above the limit, so it is only looked up when raising it
+    if max_volume < 1.0 && db > audio.get_volume_db()? {
+        db = db.min(audio.volume_to_db(max_volume)?);
+    }
+
+    audio.set_volume_db(db)?;
+    pull_back(audio, max_volume)
+}
+
+/// Lowers the volume to `max_volume` if it ended up above it, and returns the volume.
+fn pull_back<E: AudioEndpoint>(audio: &E, max_volume: f32) -> Result<f32> {
+    let volume = audio.get_volume()?;
+    if volume > max_volume {
+        audio.set_volume(max_volume)?;
+        return Ok(max_volume);
+    }
+
+    Ok(volume)
+}
+
+/// Sets the mute state, or toggles it when `muted` is `None`, and returns the new state.
+pub fn change_mute<E: AudioEndpoint>(audio: &E, muted: Option<bool>) -> Result<bool> {
+    let muted = match muted {
+        Some(muted) => muted,
+        None => !audio.get_mute_state()?,
+    };
+    audio.set_mute_state(muted)?;
+
+    Ok(muted)
+}
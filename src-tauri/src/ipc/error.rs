@@ -1,3 +1,4 @@
+use serde::{ser::SerializeMap, Serializer};
 use thiserror::Error;
 
 #[derive(serde::Serialize, Debug, Clone)]
@@ -7,11 +8,244 @@ pub enum UnexpectedErr {
     MPSCClosedError,
 }
 
-#[derive(serde::Serialize, Debug, Clone, Error)]
-#[serde(tag = "type")]
+/// Errors handed to the frontend.
+///
+/// Each variant is serialized with a stable `code` and whether retrying may help, so callers can
+/// tell a device which is briefly gone from a request which can never succeed.
+#[derive(Debug, Clone, Error)]
 pub enum APIError {
     #[error("Unexpected error: {inner:?}")]
     Unexpected { inner: UnexpectedErr },
     #[error("SomethingWrong: {msg:?}")]
     SomethingWrong { msg: String },
+    #[error("No such audio: {id:?}")]
+    DeviceNotFound { id: String },
+    /// The device was unplugged or disabled while it was being used.
+    #[error("The device is no longer available")]
+    DeviceUnavailable,
+    #[error("Invalid {field}: {reason}")]
+    InvalidArgument { field: String, reason: String },
+    /// The platform's audio service is not running, e.g. while it restarts.
+    #[error("The audio service is not available")]
+    AudioServiceUnavailable,
+    #[error("{context} failed with HRESULT {hresult:#010x}")]
+    Platform { hresult: i32, context: String },
+    /// The settings file is broken, so saving over it is refused until it is fixed or removed.
+    #[error("{path} could not be read, fix or remove it before saving: {reason}")]
+    SettingsUnreadable { path: String, reason: String },
+}
+
+impl APIError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            APIError::Unexpected { .. } => "UNEXPECTED",
+            APIError::SomethingWrong { .. } => "SOMETHING_WRONG",
+            APIError::DeviceNotFound { .. } => "DEVICE_NOT_FOUND",
+            APIError::DeviceUnavailable => "DEVICE_UNAVAILABLE",
+            APIError::InvalidArgument { .. } => "INVALID_ARGUMENT",
+            APIError::AudioServiceUnavailable => "AUDIO_SERVICE_UNAVAILABLE",
+            APIError::Platform { .. } => "PLATFORM",
+            APIError::SettingsUnreadable { .. } => "SETTINGS_UNREADABLE",
+        }
+    }
+
+    /// Whether the same request may succeed later without being changed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            APIError::DeviceUnavailable | APIError::AudioServiceUnavailable
+        )
+    }
+
+    pub fn invalid_argument(field: &str, reason: impl ToString) -> Self {
+        APIError::InvalidArgument {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Classifies an error from the audio backend, `context` naming the call which failed.
+    ///
+    /// An `APIError` raised further down is kept as it is.
+    pub fn from_error(e: anyhow::Error, context: &str) -> Self {
+        if let Some(e) = e.downcast_ref::<APIError>() {
+            return e.clone();
+        }
+
+        #[cfg(windows)]
+        if let Some(e) = e.downcast_ref::<windows::core::Error>() {
+            use windows::Win32::Media::Audio::{
+                AUDCLNT_E_DEVICE_INVALIDATED, AUDCLNT_E_SERVICE_NOT_RUNNING,
+            };
+
+            let code = e.code();
+            return if code == AUDCLNT_E_DEVICE_INVALIDATED {
+                APIError::DeviceUnavailable
+            } else if code == AUDCLNT_E_SERVICE_NOT_RUNNING {
+                APIError::AudioServiceUnavailable
+            } else {
+                APIError::Platform {
+                    hresult: code.0,
+                    context: context.to_string(),
+                }
+            };
+        }
+
+        APIError::SomethingWrong {
+            msg: format!("{} {:?}", context, e),
+        }
+    }
+}
+
+impl serde::Serialize for APIError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("retryable", &self.is_retryable())?;
+        map.serialize_entry("message", &self.to_string())?;
+
+        match self {
+            APIError::Unexpected { inner } => map.serialize_entry("inner", inner)?,
+            APIError::SomethingWrong { msg } => map.serialize_entry("msg", msg)?,
+            APIError::DeviceNotFound { id } => map.serialize_entry("id", id)?,
+            APIError::InvalidArgument { field, reason } => {
+                map.serialize_entry("field", field)?;
+                map.serialize_entry("reason", reason)?;
+            }
+            APIError::Platform { hresult, context } => {
+                map.serialize_entry("hresult", hresult)?;
+                map.serialize_entry("context", context)?;
+            }
+            APIError::SettingsUnreadable { path, reason } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("reason", reason)?;
+            }
+            APIError::DeviceUnavailable | APIError::AudioServiceUnavailable => {}
+        }
+
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn serialize(e: APIError) -> Value {
+        serde_json::to_value(e).unwrap()
+    }
+
+    #[test]
+    fn serializes_the_code_and_message_with_the_fields() {
+        assert_eq!(
+            serialize(APIError::DeviceNotFound {
+                id: "{0.0.0}".to_string()
+            }),
+            json!({
+                "code": "DEVICE_NOT_FOUND",
+                "retryable": false,
+                "message": "No such audio: \"{0.0.0}\"",
+                "id": "{0.0.0}",
+            })
+        );
+        assert_eq!(
+            serialize(APIError::invalid_argument("volume", "must be in [0, 1]")),
+            json!({
+                "code": "INVALID_ARGUMENT",
+                "retryable": false,
+                "message": "Invalid volume: must be in [0, 1]",
+                "field": "volume",
+                "reason": "must be in [0, 1]",
+            })
+        );
+        assert_eq!(
+            serialize(APIError::Platform {
+                hresult: 0x80070005_u32 as i32,
+                context: "@audio.set_volume".to_string(),
+            }),
+            json!({
+                "code": "PLATFORM",
+                "retryable": false,
+                "message": "@audio.set_volume failed with HRESULT 0x80070005",
+                "hresult": 0x80070005_u32 as i32,
+                "context": "@audio.set_volume",
+            })
+        );
+        assert_eq!(
+            serialize(APIError::Unexpected {
+                inner: UnexpectedErr::LockError
+            })["inner"],
+            json!({ "kind": "LockError" })
+        );
+    }
+
+    #[test]
+    fn marks_transient_errors_as_retryable() {
+        assert_eq!(
+            serialize(APIError::DeviceUnavailable),
+            json!({
+                "code": "DEVICE_UNAVAILABLE",
+                "retryable": true,
+                "message": "The device is no longer available",
+            })
+        );
+        assert_eq!(
+            serialize(APIError::AudioServiceUnavailable),
+            json!({
+                "code": "AUDIO_SERVICE_UNAVAILABLE",
+                "retryable": true,
+                "message": "The audio service is not available",
+            })
+        );
+        assert_eq!(
+            serialize(APIError::SomethingWrong {
+                msg: "oops".to_string()
+            })["retryable"],
+            json!(false)
+        );
+    }
+
+    #[test]
+    fn keeps_an_api_error_raised_further_down() {
+        let e = anyhow::Error::from(APIError::DeviceUnavailable).context("@audio.set_volume");
+
+        assert!(matches!(
+            APIError::from_error(e, "@control.step_volume"),
+            APIError::DeviceUnavailable
+        ));
+        assert!(matches!(
+            APIError::from_error(anyhow::anyhow!("oops"), "@audio.get_volume"),
+            APIError::SomethingWrong { .. }
+        ));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn classifies_platform_errors_by_hresult() {
+        use windows::{
+            core::HRESULT,
+            Win32::Media::Audio::{AUDCLNT_E_DEVICE_INVALIDATED, AUDCLNT_E_SERVICE_NOT_RUNNING},
+        };
+
+        let classify = |hr: HRESULT| {
+            let e = anyhow::Error::from(hr.ok().unwrap_err());
+            APIError::from_error(e, "@audio.set_as_default")
+        };
+
+        assert!(matches!(
+            classify(AUDCLNT_E_DEVICE_INVALIDATED),
+            APIError::DeviceUnavailable
+        ));
+        assert!(matches!(
+            classify(AUDCLNT_E_SERVICE_NOT_RUNNING),
+            APIError::AudioServiceUnavailable
+        ));
+        assert!(matches!(
+            classify(HRESULT(0x80070005_u32 as i32)),
+            APIError::Platform { hresult, context }
+                if hresult == 0x80070005_u32 as i32 && context == "@audio.set_as_default"
+        ));
+    }
 }
@@ -1,54 +1,290 @@
 use anyhow::Result;
-use std::collections::BTreeMap;
-use std::sync::{Arc, Mutex};
+use std::mem::replace;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
 use tauri::{App, Emitter, Manager, Wry};
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::{
+    mpsc::{channel, Receiver, Sender},
+    oneshot, watch, Mutex as AsyncMutex,
+};
 use tokio::task::JoinHandle;
-use tokio::time::{timeout, Duration};
+use tokio::time::{timeout_at, Duration, Instant};
+
+use crate::tray;
 
 use super::{
-    audio::{notifier::Notification, IMMAudioDevice, Singleton},
+    audio::{notifier::Notification, AudioBackend, AudioEndpoint, Role},
+    control,
     error::*,
-    sender::{ipc_sender, AudioStateChangePayload},
+    meter::{self, MeterPayload},
+    policy,
+    profile::ProfileReport,
+    sender::{ipc_sender, AudioDeviceMap, AudioStateChangePayload, Report},
+    settings::Settings,
+    state::BackendState,
+    supervisor::{self, BackendStatus, Generation},
 };
 
-type AudioDict = BTreeMap<String, IMMAudioDevice>;
-
 #[derive(serde::Deserialize, Debug, Clone)]
 #[serde(tag = "kind")]
 pub enum IPCHandlers {
-    AudioDictUpdate { notification: Notification },
+    /// Every notification of a batch, as policies must not miss one that came before the last.
+    ///
+    /// Sent by the relay only, so like the other internal queries it cannot come from the window.
+    #[serde(skip)]
+    AudioDictUpdate {
+        notifications: Vec<Notification>,
+    },
+    #[serde(skip)]
+    SessionUpdate {
+        notification: Notification,
+    },
     AudioDict,
-    DefaultAudioChange { id: String },
-    VolumeChange { id: String, volume: f32 },
-    MuteStateChange { id: String, muted: bool },
+    /// `roles` defaults to every role, which is what the Sound control panel does.
+    DefaultAudioChange {
+        id: String,
+        roles: Option<Vec<Role>>,
+    },
+    VolumeChange {
+        id: String,
+        volume: f32,
+    },
+    /// Clamped into the device's volume range.
+    VolumeDbChange {
+        id: String,
+        db: f32,
+    },
+    MuteStateChange {
+        id: String,
+        muted: bool,
+    },
+    SessionVolumeChange {
+        id: String,
+        pid: u32,
+        volume: f32,
+    },
+    SessionMuteChange {
+        id: String,
+        pid: u32,
+        muted: bool,
+    },
+    AppDeviceChange {
+        app: String,
+        id: Option<String>,
+    },
+    ProfileSave {
+        name: String,
+    },
+    ProfileApply {
+        name: String,
+    },
+    ProfileRemove {
+        name: String,
+    },
+    /// Sent once the settings are validated and saved, which `update_settings` takes care of.
+    #[serde(skip)]
+    SettingsUpdate {
+        settings: Settings,
+    },
+    /// `device` is an id or a device name, for callers which only know the name.
+    SwitchDevice {
+        device: String,
+    },
+    /// Switches to the output after the default one.
+    SwitchNext,
+    /// Targets the default output when `device` is `None`.
+    VolumeStep {
+        device: Option<String>,
+        step: f32,
+    },
+    /// One step of the device's own volume control, for the default output when `device` is
+    /// `None`.
+    VolumeHardwareStep {
+        device: Option<String>,
+        up: bool,
+    },
+    /// Toggles the mute state when `muted` is `None`.
+    MuteChange {
+        device: Option<String>,
+        muted: Option<bool>,
+    },
+    /// Sent by the window as it is shown and hidden, so peaks are only sampled while visible.
+    Metering {
+        enabled: bool,
+    },
+}
+
+/// Outcome of a query, handed back to whoever sent it.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum QueryResult {
+    /// The query has nothing to report beyond having succeeded.
+    Done,
+    /// The volume the device was set to, after any limit was applied.
+    Volume {
+        id: String,
+        volume: f32,
+    },
+    Muted {
+        id: String,
+        muted: bool,
+    },
+    /// The device which became the default.
+    DefaultChanged {
+        id: String,
+    },
+    ProfileApplied(ProfileReport),
+}
+
+pub type Reply = oneshot::Sender<Result<QueryResult, APIError>>;
+
+/// A query on its way to the backend, with a channel for the result when the sender waits.
+pub struct Request {
+    pub query: IPCHandlers,
+    /// `None` for senders which do not wait, such as the relay, the tray and hotkeys.
+    pub reply: Option<Reply>,
+}
+
+impl Request {
+    pub fn new(query: IPCHandlers) -> (Self, oneshot::Receiver<Result<QueryResult, APIError>>) {
+        let (tx, rx) = oneshot::channel();
+
+        (
+            Self {
+                query,
+                reply: Some(tx),
+            },
+            rx,
+        )
+    }
+}
+
+impl From<IPCHandlers> for Request {
+    fn from(query: IPCHandlers) -> Self {
+        Self { query, reply: None }
+    }
 }
 
 const RECEIVE_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct BackendPrepareRet {
-    pub relay_thread: JoinHandle<Result<()>>,
-    pub backend_thread: JoinHandle<Result<(), APIError>>,
-    pub ipc_tx: Sender<IPCHandlers>,
+    pub supervisor_thread: JoinHandle<()>,
+    pub settings_thread: JoinHandle<Result<()>>,
+    pub ipc_tx: Sender<Request>,
     pub ipc_rx: Receiver<AudioStateChangePayload>,
+    pub meter_rx: Receiver<MeterPayload>,
+    pub status_rx: Receiver<BackendStatus>,
 }
 
-pub async fn prepare_backend() -> Result<BackendPrepareRet> {
-    let (backend_update_tx, mut backend_update_rx) = channel(256);
+/// Spawns the backend built by `init` together with its relay, under a supervisor which builds
+/// them again whenever they stop.
+///
+/// Files the backend keeps between runs are stored in `config_dir`, and changes to the settings
+/// are picked up from `settings_rx`.
+pub async fn prepare_backend<B, F>(
+    init: F,
+    config_dir: PathBuf,
+    mut settings_rx: watch::Receiver<Settings>,
+) -> Result<BackendPrepareRet>
+where
+    B: AudioBackend,
+    F: Fn(&Sender<Notification>) -> Result<B> + Send + Sync + 'static,
+{
     let (frontend_update_tx, ipc_rx) = channel(256);
-    let (ipc_tx, mut query_rx) = channel(256);
+    let (ipc_tx, query_rx) = channel(256);
+    let (meter_tx, meter_rx) = channel(4);
+    let (status_tx, status_rx) = channel(16);
+
+    let shared = Shared {
+        init: Arc::new(init),
+        config_dir,
+        settings_rx: settings_rx.clone(),
+        query_rx: Arc::new(AsyncMutex::new(query_rx)),
+        frontend_update_tx,
+        meter_tx,
+    };
+
+    let qt = ipc_tx.clone();
+    // the first backend starts from the current settings, so only later changes are sent
+    settings_rx.borrow_and_update();
+    let settings_thread = tokio::spawn(async move {
+        while settings_rx.changed().await.is_ok() {
+            let settings = settings_rx.borrow_and_update().clone();
+            qt.send(IPCHandlers::SettingsUpdate { settings }.into())
+                .await
+                .map_err(|_| APIError::Unexpected {
+                    inner: UnexpectedErr::MPSCClosedError,
+                })?;
+        }
+
+        Result::<()>::Ok(())
+    });
 
     let qt = ipc_tx.clone();
-    let relay_thread = tokio::spawn(async move {
-        while let Some(mut notification) = backend_update_rx.recv().await {
+    let supervisor_thread = supervisor::spawn(
+        move || {
+            // a channel per backend, so the relay stops once the backend and its callbacks are gone
+            let (backend_update_tx, backend_update_rx) = channel(256);
+            let (ready_tx, ready) = oneshot::channel();
+
+            Generation {
+                relay_thread: spawn_relay(backend_update_rx, qt.clone()),
+                backend_thread: shared.spawn_backend(backend_update_tx, ready_tx),
+                ready,
+            }
+        },
+        status_tx,
+    );
+
+    Ok(BackendPrepareRet {
+        supervisor_thread,
+        settings_thread,
+        ipc_tx,
+        ipc_rx,
+        meter_rx,
+        status_rx,
+    })
+}
+
+/// Batches notifications from the backend into queries.
+fn spawn_relay(
+    mut backend_update_rx: Receiver<Notification>,
+    qt: Sender<Request>,
+) -> JoinHandle<Result<()>> {
+    tokio::spawn(async move {
+        while let Some(notification) = backend_update_rx.recv().await {
+            // a steady stream of session notifications must not hold back the batch forever
+            let deadline = Instant::now() + RECEIVE_INTERVAL;
+            let mut sessions_only = notification.is_session();
+            let mut urgent = notification.is_urgent();
+            let mut notifications = vec![notification];
             loop {
-                match timeout(RECEIVE_INTERVAL, backend_update_rx.recv()).await {
-                    Ok(Some(n)) => notification = n,
-                    _ => break,
-                }
+                let n = if urgent {
+                    // what has already arrived still goes along, like the other roles' defaults
+                    match backend_update_rx.try_recv() {
+                        Ok(n) => n,
+                        Err(_) => break,
+                    }
+                } else {
+                    match timeout_at(deadline, backend_update_rx.recv()).await {
+                        Ok(Some(n)) => n,
+                        _ => break,
+                    }
+                };
+                sessions_only &= n.is_session();
+                urgent |= n.is_urgent();
+                notifications.push(n);
             }
 
-            qt.send(IPCHandlers::AudioDictUpdate { notification })
+            let query = match notifications.pop() {
+                Some(notification) if sessions_only => IPCHandlers::SessionUpdate { notification },
+                last => {
+                    notifications.extend(last);
+                    IPCHandlers::AudioDictUpdate { notifications }
+                }
+            };
+
+            qt.send(query.into())
                 .await
                 .map_err(|_| APIError::Unexpected {
                     inner: UnexpectedErr::MPSCClosedError,
@@ -56,154 +292,437 @@ pub async fn prepare_backend() -> Result<BackendPrepareRet> {
         }
 
         Result::<()>::Ok(())
-    });
+    })
+}
 
-    let backend_thread = tokio::spawn(async move {
-        let is =
-            Arc::new(
-                Singleton::new(&backend_update_tx).map_err(|e| APIError::SomethingWrong {
-                    msg: format!("@InstantsSingleton::new {:?}", e),
-                })?,
+/// What outlives each backend the supervisor builds.
+struct Shared<F> {
+    init: Arc<F>,
+    config_dir: PathBuf,
+    settings_rx: watch::Receiver<Settings>,
+    /// Held by the running backend, so queries sent during a restart wait for the next one.
+    query_rx: Arc<AsyncMutex<Receiver<Request>>>,
+    frontend_update_tx: Sender<AudioStateChangePayload>,
+    meter_tx: Sender<MeterPayload>,
+}
+
+impl<F> Shared<F> {
+    fn spawn_backend<B>(
+        &self,
+        backend_update_tx: Sender<Notification>,
+        ready: oneshot::Sender<()>,
+    ) -> JoinHandle<Result<(), APIError>>
+    where
+        B: AudioBackend,
+        F: Fn(&Sender<Notification>) -> Result<B> + Send + Sync + 'static,
+    {
+        let init = Arc::clone(&self.init);
+        let config_dir = self.config_dir.clone();
+        // updates still queued from before a restart are applied on top of this
+        let settings = self.settings_rx.borrow().clone();
+        let query_rx = Arc::clone(&self.query_rx);
+        let frontend_update_tx = self.frontend_update_tx.clone();
+        let meter_tx = self.meter_tx.clone();
+
+        tokio::spawn(async move {
+            let is = Arc::new(
+                init(&backend_update_tx)
+                    .map_err(|e| APIError::from_error(e, "@AudioBackend::new"))?,
             );
 
-        let audio_dict = Arc::new(Mutex::new(get_audio_dictionary(&(is)).map_err(|e| {
-            APIError::SomethingWrong {
-                msg: format!("@get_audio_dict {:?}", e),
+            let dict = get_audio_dictionary(&(is))
+                .map_err(|e| APIError::from_error(e, "@get_audio_dict"))?;
+
+            let mut state = BackendState::open(&config_dir, settings);
+            state.policy.remember_default(&*is, &dict);
+            // devices may have been raised above their limit while nothing was watching
+            let reports = policy::limit_all_volumes(&dict, &state.preferences);
+
+            let audio_dict = Arc::new(Mutex::new(dict));
+
+            let (meter_interval_tx, meter_interval_rx) = watch::channel(None);
+            meter::spawn(Arc::clone(&audio_dict), meter_interval_rx, meter_tx);
+
+            let mut backend = Backend {
+                is,
+                audio_dict,
+                state,
+                frontend_update_tx,
+                metering: false,
+                meter_interval_tx,
+            };
+
+            // the tray menu is built from this before the window asks for anything
+            backend.send_state(None, reports).await;
+            let _ = ready.send(());
+
+            // released when this task ends, even by a panic, so the next backend can take over
+            let mut query_rx = query_rx.lock_owned().await;
+            while let Some(Request { query, reply }) = query_rx.recv().await {
+                let update = matches!(query, IPCHandlers::AudioDictUpdate { .. });
+                let result = backend.handle(query).await;
+
+                // the dict is poisoned or stale from here on, which only a new backend fixes
+                let fatal = match &result {
+                    Err(
+                        e @ APIError::Unexpected {
+                            inner: UnexpectedErr::LockError,
+                        },
+                    ) => Some(e.clone()),
+                    Err(e) if update => Some(e.clone()),
+                    _ => None,
+                };
+                if let Err(e) = &result {
+                    log::error!("{:?}", e);
+                }
+
+                // the caller may have given up waiting, which is not an error here
+                if let Some(reply) = reply {
+                    let _ = reply.send(result);
+                }
+
+                if let Some(e) = fatal {
+                    return Err(e);
+                }
             }
-        })?));
 
-        while let Some(q) = query_rx.recv().await {
-            match q {
-                IPCHandlers::AudioDictUpdate { notification } => {
-                    {
-                        let mut dict = audio_dict.lock().map_err(|_| APIError::Unexpected {
-                            inner: UnexpectedErr::LockError,
-                        })?;
-                        *dict =
-                            get_audio_dictionary(&is).map_err(|e| APIError::SomethingWrong {
-                                msg: format!("@get_audio_dict {:?}", e),
-                            })?;
-                    }
-                    let e =
-                        ipc_sender(&is, &audio_dict, Some(notification), &frontend_update_tx).await;
+            Result::<(), APIError>::Ok(())
+        })
+    }
+}
 
-                    if let Err(e) = e {
-                        log::error!("{:?}", e);
-                        // continue;
-                    }
+/// What the backend loop owns, so each query can be handled in a function of its own.
+struct Backend<B: AudioBackend> {
+    is: Arc<B>,
+    audio_dict: Arc<Mutex<AudioDeviceMap<B::Endpoint>>>,
+    state: BackendState,
+    frontend_update_tx: Sender<AudioStateChangePayload>,
+    metering: bool,
+    meter_interval_tx: watch::Sender<Option<Duration>>,
+}
+
+impl<B: AudioBackend> Backend<B> {
+    /// Pushes the whole state to the UI. A failure here does not fail the query which caused it.
+    async fn send_state(&self, notification: Option<Notification>, reports: Vec<Report>) {
+        let e = ipc_sender(
+            &self.is,
+            &self.audio_dict,
+            &self.state,
+            notification,
+            reports,
+            &self.frontend_update_tx,
+        )
+        .await;
+
+        if let Err(e) = e {
+            log::error!("{:?}", e);
+        }
+    }
+
+    fn update_meter(&self) {
+        let interval = self
+            .metering
+            .then(|| meter::sample_interval(self.state.settings.meter_rate));
+        self.meter_interval_tx
+            .send_if_modified(|current| replace(current, interval) != interval);
+    }
+
+    async fn handle(&mut self, query: IPCHandlers) -> Result<QueryResult, APIError> {
+        let is = &*self.is;
+
+        match query {
+            IPCHandlers::AudioDictUpdate { mut notifications } => {
+                let reports = {
+                    let mut dict = lock_dict(&self.audio_dict)?;
+                    let state = &mut self.state;
+                    let mut reports = state.policy.on_notifications_early(
+                        is,
+                        &dict,
+                        &state.settings,
+                        &notifications,
+                    );
+
+                    *dict = get_audio_dictionary(&self.is)
+                        .map_err(|e| APIError::from_error(e, "@get_audio_dict"))?;
+
+                    state.app_routing.enforce(is, &dict);
+                    state
+                        .volume_memory
+                        .on_notifications(&dict, &state.preferences, &notifications);
+
+                    reports.extend(state.policy.on_notifications(
+                        is,
+                        &dict,
+                        &state.settings,
+                        &state.preferences,
+                        &notifications,
+                    ));
+                    reports
+                };
+
+                self.send_state(notifications.pop(), reports).await;
+            }
+            IPCHandlers::SessionUpdate { notification } => {
+                // sessions are read from the devices already held in audio_dict
+                {
+                    let dict = lock_dict(&self.audio_dict)?;
+                    self.state.app_routing.enforce(is, &dict);
                 }
-                IPCHandlers::AudioDict => {
-                    let e = ipc_sender(&is, &audio_dict, None, &frontend_update_tx).await;
 
-                    if let Err(e) = e {
-                        log::error!("update_notifing_b2f {:?}", e);
-                        // continue;
-                    }
+                self.send_state(Some(notification), Vec::new()).await;
+            }
+            IPCHandlers::AudioDict => {
+                self.send_state(None, Vec::new()).await;
+            }
+            IPCHandlers::DefaultAudioChange { id, roles } => {
+                let dict = lock_dict(&self.audio_dict)?;
+                let audio = get_device(&dict, &id)?;
+
+                let roles = roles.unwrap_or_else(|| Role::ALL.to_vec());
+                if roles.is_empty() {
+                    return Err(APIError::invalid_argument(
+                        "roles",
+                        "must name at least one role",
+                    ));
                 }
-                IPCHandlers::DefaultAudioChange { id } => {
-                    let dict = audio_dict.lock().map_err(|_| APIError::Unexpected {
-                        inner: UnexpectedErr::LockError,
-                    })?;
-
-                    let audio_w = dict.get(&id).ok_or(APIError::SomethingWrong {
-                        msg: format!("No such audio: {:?}", id),
-                    });
-
-                    let audio = match audio_w {
-                        Ok(audio) => audio,
-                        Err(e) => {
-                            log::error!("{:?}", e);
-                            continue;
-                        }
-                    };
-
-                    let e = audio
-                        .set_as_default()
-                        .map_err(|e| APIError::SomethingWrong {
-                            msg: format!("audio.set_as_default {:?}", e),
-                        });
-
-                    if let Err(e) = e {
-                        log::error!("{:?}", e);
-                        // continue;
-                    }
+                audio
+                    .set_as_default(&roles)
+                    .map_err(|e| APIError::from_error(e, "@audio.set_as_default"))?;
+
+                return Ok(QueryResult::DefaultChanged { id });
+            }
+            IPCHandlers::VolumeChange { id, volume } => {
+                check_volume("volume", volume)?;
+
+                let dict = lock_dict(&self.audio_dict)?;
+                let audio = get_device(&dict, &id)?;
+
+                let volume = self.state.preferences.limit_volume(&id, volume);
+                audio
+                    .set_volume(volume)
+                    .map_err(|e| APIError::from_error(e, "@audio.set_volume"))?;
+
+                return Ok(QueryResult::Volume { id, volume });
+            }
+            IPCHandlers::VolumeDbChange { id, db } => {
+                if !db.is_finite() {
+                    return Err(APIError::invalid_argument("db", "must be finite"));
                 }
-                IPCHandlers::VolumeChange { id, volume } => {
-                    let dict = audio_dict.lock().map_err(|_| APIError::Unexpected {
-                        inner: UnexpectedErr::LockError,
-                    })?;
-
-                    let audio_w = dict.get(&id).ok_or(APIError::SomethingWrong {
-                        msg: format!("No such audio: {:?}", id),
-                    });
-
-                    let audio = match audio_w {
-                        Ok(audio) => audio,
-                        Err(e) => {
-                            log::error!("{:?}", e);
-                            continue;
-                        }
-                    };
-
-                    let e = audio
-                        .set_volume(volume)
-                        .map_err(|e| APIError::SomethingWrong {
-                            msg: format!("@audio.set_volume {:?}", e),
-                        });
-
-                    if let Err(e) = e {
-                        log::error!("{:?}", e);
-                        // continue;
-                    }
+
+                let dict = lock_dict(&self.audio_dict)?;
+                let audio = get_device(&dict, &id)?;
+
+                let max_volume = self.state.preferences.max_volume(&id);
+                let volume = control::set_volume_db(audio, db, max_volume)
+                    .map_err(|e| APIError::from_error(e, "@control.set_volume_db"))?;
+
+                return Ok(QueryResult::Volume { id, volume });
+            }
+            IPCHandlers::MuteStateChange { id, muted } => {
+                let dict = lock_dict(&self.audio_dict)?;
+                let audio = get_device(&dict, &id)?;
+
+                audio
+                    .set_mute_state(muted)
+                    .map_err(|e| APIError::from_error(e, "@audio.set_mute"))?;
+
+                return Ok(QueryResult::Muted { id, muted });
+            }
+            IPCHandlers::SessionVolumeChange { id, pid, volume } => {
+                check_volume("volume", volume)?;
+
+                let dict = lock_dict(&self.audio_dict)?;
+                let audio = get_device(&dict, &id)?;
+
+                audio
+                    .set_session_volume(pid, volume)
+                    .map_err(|e| APIError::from_error(e, "@audio.set_session_volume"))?;
+            }
+            IPCHandlers::SessionMuteChange { id, pid, muted } => {
+                let dict = lock_dict(&self.audio_dict)?;
+                let audio = get_device(&dict, &id)?;
+
+                audio
+                    .set_session_mute_state(pid, muted)
+                    .map_err(|e| APIError::from_error(e, "@audio.set_session_mute"))?;
+            }
+            IPCHandlers::AppDeviceChange { app, id } => {
+                {
+                    let dict = lock_dict(&self.audio_dict)?;
+                    self.state
+                        .app_routing
+                        .set_route(is, &dict, &app, id)
+                        .map_err(|e| APIError::from_error(e, "@app_routing.set_route"))?;
                 }
-                IPCHandlers::MuteStateChange { id, muted } => {
-                    let dict = audio_dict.lock().map_err(|_| APIError::Unexpected {
-                        inner: UnexpectedErr::LockError,
-                    })?;
-
-                    let audio_w = dict.get(&id).ok_or(APIError::SomethingWrong {
-                        msg: format!("No such audio: {:?}", id),
-                    });
-
-                    let audio = match audio_w {
-                        Ok(audio) => audio,
-                        Err(e) => {
-                            log::error!("{:?}", e);
-                            continue;
-                        }
-                    };
-
-                    let e = audio
-                        .set_mute_state(muted)
-                        .map_err(|e| APIError::SomethingWrong {
-                            msg: format!("@audio.set_mute {:?}", e),
-                        });
-
-                    if let Err(e) = e {
-                        log::error!("{:?}", e);
-                        // continue;
-                    }
+
+                // routes are not reported by the platform, so push them to the UI here
+                self.send_state(None, Vec::new()).await;
+            }
+            IPCHandlers::ProfileSave { name } => {
+                {
+                    let dict = lock_dict(&self.audio_dict)?;
+                    self.state
+                        .profiles
+                        .save(is, &dict, &name)
+                        .map_err(|e| APIError::from_error(e, "@profiles.save"))?;
+                }
+
+                self.send_state(None, Vec::new()).await;
+            }
+            IPCHandlers::ProfileApply { name } => {
+                let report = {
+                    let dict = lock_dict(&self.audio_dict)?;
+                    self.state
+                        .profiles
+                        .apply(&dict, &self.state.preferences, &name)
+                        .map_err(|e| APIError::from_error(e, "@profiles.apply"))?
+                };
+
+                if report.is_partial() {
+                    log::warn!("Profile applied partially: {:?}", report);
+                }
+
+                // the resulting device changes arrive as notifications, the report does not
+                self.send_state(None, vec![Report::ProfileApplied(report.clone())])
+                    .await;
+
+                return Ok(QueryResult::ProfileApplied(report));
+            }
+            IPCHandlers::ProfileRemove { name } => {
+                self.state
+                    .profiles
+                    .remove(&name)
+                    .map_err(|e| APIError::from_error(e, "@profiles.remove"))?;
+
+                self.send_state(None, Vec::new()).await;
+            }
+            IPCHandlers::SettingsUpdate { settings } => {
+                self.state.set_settings(settings);
+                self.update_meter();
+
+                // a lowered limit applies right away
+                let reports = {
+                    let dict = lock_dict(&self.audio_dict)?;
+                    policy::limit_all_volumes(&dict, &self.state.preferences)
+                };
+
+                self.send_state(None, reports).await;
+            }
+            IPCHandlers::SwitchDevice { device } => {
+                let dict = lock_dict(&self.audio_dict)?;
+
+                let id = control::find_device(&dict, &device)
+                    .and_then(|audio| control::switch_device(audio).map(|_| audio.id()))
+                    .map_err(|e| APIError::from_error(e, "@control.switch_device"))?;
+
+                return Ok(QueryResult::DefaultChanged { id: id.to_string() });
+            }
+            IPCHandlers::SwitchNext => {
+                let dict = lock_dict(&self.audio_dict)?;
+
+                let id = control::next_device(is, &dict, &self.state.preferences)
+                    .and_then(|audio| control::switch_device(audio).map(|_| audio.id()))
+                    .map_err(|e| APIError::from_error(e, "@control.switch_device"))?;
+
+                return Ok(QueryResult::DefaultChanged { id: id.to_string() });
+            }
+            IPCHandlers::VolumeStep { device, step } => {
+                if !step.is_finite() {
+                    return Err(APIError::invalid_argument("step", "must be finite"));
                 }
+
+                let dict = lock_dict(&self.audio_dict)?;
+
+                let (id, volume) = control::target_device(is, &dict, device.as_deref())
+                    .and_then(|audio| {
+                        let max_volume = self.state.preferences.max_volume(audio.id());
+                        let volume = control::step_volume(audio, step, max_volume)?;
+                        Ok((audio.id().to_string(), volume))
+                    })
+                    .map_err(|e| APIError::from_error(e, "@control.step_volume"))?;
+
+                return Ok(QueryResult::Volume { id, volume });
+            }
+            IPCHandlers::VolumeHardwareStep { device, up } => {
+                let dict = lock_dict(&self.audio_dict)?;
+
+                let (id, volume) = control::target_device(is, &dict, device.as_deref())
+                    .and_then(|audio| {
+                        let max_volume = self.state.preferences.max_volume(audio.id());
+                        let volume = control::hardware_step(audio, up, max_volume)?;
+                        Ok((audio.id().to_string(), volume))
+                    })
+                    .map_err(|e| APIError::from_error(e, "@control.hardware_step"))?;
+
+                return Ok(QueryResult::Volume { id, volume });
+            }
+            IPCHandlers::MuteChange { device, muted } => {
+                let dict = lock_dict(&self.audio_dict)?;
+
+                let (id, muted) = control::target_device(is, &dict, device.as_deref())
+                    .and_then(|audio| {
+                        let muted = control::change_mute(audio, muted)?;
+                        Ok((audio.id().to_string(), muted))
+                    })
+                    .map_err(|e| APIError::from_error(e, "@control.change_mute"))?;
+
+                return Ok(QueryResult::Muted { id, muted });
+            }
+            IPCHandlers::Metering { enabled } => {
+                self.metering = enabled;
+                self.update_meter();
             }
         }
 
-        Result::<(), APIError>::Ok(())
-    });
+        Ok(QueryResult::Done)
+    }
+}
 
-    Ok(BackendPrepareRet {
-        relay_thread,
-        backend_thread,
-        ipc_tx,
-        ipc_rx,
+/// Takes the dict by itself rather than through `Backend`, so the state stays free to borrow.
+fn lock_dict<E: AudioEndpoint>(
+    audio_dict: &Mutex<AudioDeviceMap<E>>,
+) -> Result<MutexGuard<'_, AudioDeviceMap<E>>, APIError> {
+    audio_dict.lock().map_err(|_| APIError::Unexpected {
+        inner: UnexpectedErr::LockError,
     })
 }
 
-pub fn setup(app: &mut App<Wry>, mut rx: Receiver<AudioStateChangePayload>) -> JoinHandle<()> {
+fn get_device<'a, E: AudioEndpoint>(
+    audio_dict: &'a AudioDeviceMap<E>,
+    id: &str,
+) -> Result<&'a E, APIError> {
+    audio_dict
+        .get(id)
+        .ok_or_else(|| APIError::DeviceNotFound { id: id.to_string() })
+}
+
+/// Rejects volumes the platform would either refuse or silently misread.
+fn check_volume(field: &str, volume: f32) -> Result<(), APIError> {
+    if !(0.0..=1.0).contains(&volume) {
+        return Err(APIError::invalid_argument(
+            field,
+            format!("{} is not in [0, 1]", volume),
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn setup(
+    app: &mut App<Wry>,
+    mut rx: Receiver<AudioStateChangePayload>,
+    mut meter_rx: Receiver<MeterPayload>,
+    mut status_rx: Receiver<BackendStatus>,
+) -> JoinHandle<()> {
     let main_window = app.get_webview_window("main").unwrap();
+    let app_handle = app.handle().clone();
 
     let mw = main_window.clone();
     let notification_thread = tokio::spawn(async move {
+        let mut tray_view = tray::TrayView::default();
         while let Some(unb2f) = rx.recv().await {
+            tray_view.update(&app_handle, unb2f.windows_audio_state());
+
             let e = mw.emit("audio_state_change", unb2f);
             if let Err(e) = e {
                 log::error!("{:?}", e);
@@ -211,14 +730,260 @@ pub fn setup(app: &mut App<Wry>, mut rx: Receiver<AudioStateChangePayload>) -> J
         }
     });
 
+    // a separate event, as peaks arrive many times a second and carry no device state
+    let mw = main_window.clone();
+    tokio::spawn(async move {
+        while let Some(payload) = meter_rx.recv().await {
+            if let Err(e) = mw.emit("peak_meter", payload) {
+                log::error!("{:?}", e);
+            }
+        }
+    });
+
+    let mw = main_window.clone();
+    tokio::spawn(async move {
+        while let Some(status) = status_rx.recv().await {
+            if let Err(e) = mw.emit("backend_status", status) {
+                log::error!("{:?}", e);
+            }
+        }
+    });
+
     notification_thread
 }
 
-fn get_audio_dictionary(is: &Arc<Singleton>) -> Result<AudioDict> {
-    let res = Singleton::get_active_audio_devices(is)?
+pub fn get_audio_dictionary<B: AudioBackend>(is: &Arc<B>) -> Result<AudioDeviceMap<B::Endpoint>> {
+    let res = is
+        .get_active_audio_devices()?
         .into_iter()
-        .map(|a| (a.id.clone(), a))
+        .map(|a| (a.id().to_string(), a))
         .collect();
 
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::ipc::{
+        audio::{
+            fake::{FakeBackend, FakeDeviceState},
+            DataFlow,
+        },
+        sender::WindowsAudioState,
+        testing,
+    };
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    /// A backend loop over a fake, started the way the app starts the real one.
+    struct Harness {
+        ret: BackendPrepareRet,
+        /// Shares its devices with the fake the running backend owns.
+        fake: Arc<Mutex<Option<FakeBackend>>>,
+        _settings_tx: watch::Sender<Settings>,
+    }
+
+    impl Harness {
+        /// `1-speakers` starts as the default output.
+        async fn start(name: &str) -> Self {
+            Self::start_failing(name, 0).await
+        }
+
+        /// Like `start`, with the backend failing to start `failures` times first.
+        async fn start_failing(name: &str, failures: u32) -> Self {
+            let fake = Arc::new(Mutex::new(None));
+            let failures = AtomicU32::new(failures);
+            let init = {
+                let fake = Arc::clone(&fake);
+                move |tx: &Sender<Notification>| -> Result<FakeBackend> {
+                    let failed = failures
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+                    if failed.is_ok() {
+                        return Err(APIError::AudioServiceUnavailable.into());
+                    }
+
+                    let is = FakeBackend::with_devices(
+                        tx,
+                        [
+                            (
+                                "1-speakers".to_string(),
+                                FakeDeviceState::new("Speakers", DataFlow::Render),
+                            ),
+                            (
+                                "2-headphones".to_string(),
+                                FakeDeviceState::new("Headphones", DataFlow::Render),
+                            ),
+                        ],
+                    );
+                    *fake.lock().unwrap() = Some(is.clone());
+                    Ok(is)
+                }
+            };
+
+            let (settings_tx, settings_rx) = watch::channel(Settings::default());
+            let ret = prepare_backend(init, testing::config_dir(name), settings_rx)
+                .await
+                .unwrap();
+
+            Self {
+                ret,
+                fake,
+                _settings_tx: settings_tx,
+            }
+        }
+
+        fn fake(&self) -> FakeBackend {
+            self.fake.lock().unwrap().clone().unwrap()
+        }
+
+        async fn query(&self, query: IPCHandlers) -> Result<QueryResult, APIError> {
+            let (request, reply) = Request::new(query);
+            self.ret.ipc_tx.send(request).await.unwrap();
+            timeout(WAIT, reply).await.unwrap().unwrap()
+        }
+
+        /// Waits for a state sent to the UI which satisfies `f`.
+        async fn state_where(
+            &mut self,
+            f: impl Fn(&WindowsAudioState) -> bool,
+        ) -> WindowsAudioState {
+            loop {
+                let payload = timeout(WAIT, self.ret.ipc_rx.recv())
+                    .await
+                    .expect("no matching state was sent")
+                    .unwrap();
+                if f(payload.windows_audio_state()) {
+                    return payload.windows_audio_state().clone();
+                }
+            }
+        }
+    }
+
+    fn volume_of(state: &WindowsAudioState, id: &str) -> Option<f32> {
+        state
+            .audio_device_list
+            .iter()
+            .find(|d| d.id == id)
+            .map(|d| d.volume)
+    }
+
+    #[tokio::test]
+    async fn sends_the_state_once_started() {
+        let mut h = Harness::start("started").await;
+
+        let state = h.state_where(|_| true).await;
+
+        assert_eq!(state.default, "1-speakers");
+        let ids: Vec<_> = state.audio_device_list.iter().map(|d| &d.id).collect();
+        assert_eq!(ids, ["1-speakers", "2-headphones"]);
+    }
+
+    #[tokio::test]
+    async fn handles_queries_through_the_backend() {
+        let mut h = Harness::start("queries").await;
+
+        let result = h
+            .query(IPCHandlers::VolumeChange {
+                id: "2-headphones".to_string(),
+                volume: 0.5,
+            })
+            .await;
+        assert!(matches!(result, Ok(QueryResult::Volume { volume, .. }) if volume == 0.5));
+        assert_eq!(h.fake().device("2-headphones").unwrap().volume, 0.5);
+        h.state_where(|s| volume_of(s, "2-headphones") == Some(0.5))
+            .await;
+
+        let result = h
+            .query(IPCHandlers::DefaultAudioChange {
+                id: "2-headphones".to_string(),
+                roles: None,
+            })
+            .await;
+        assert!(matches!(result, Ok(QueryResult::DefaultChanged { id }) if id == "2-headphones"));
+        h.state_where(|s| s.default == "2-headphones").await;
+    }
+
+    #[tokio::test]
+    async fn rejects_an_empty_role_list() {
+        let h = Harness::start("empty-roles").await;
+
+        let result = h
+            .query(IPCHandlers::DefaultAudioChange {
+                id: "2-headphones".to_string(),
+                roles: Some(Vec::new()),
+            })
+            .await;
+
+        assert!(matches!(result, Err(APIError::InvalidArgument { field, .. }) if field == "roles"));
+        for role in Role::ALL {
+            let default = h.fake().get_default_audio_id(DataFlow::Render, role);
+            assert_eq!(default.unwrap(), "1-speakers");
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_devices() {
+        let h = Harness::start("unknown").await;
+
+        let result = h
+            .query(IPCHandlers::MuteStateChange {
+                id: "no-such-device".to_string(),
+                muted: true,
+            })
+            .await;
+
+        assert!(matches!(result, Err(APIError::DeviceNotFound { id }) if id == "no-such-device"));
+    }
+
+    #[tokio::test]
+    async fn follows_notifications_from_the_backend() {
+        let mut h = Harness::start("notifications").await;
+        h.state_where(|_| true).await;
+
+        h.fake()
+            .add_device("3-tv", FakeDeviceState::new("TV", DataFlow::Render))
+            .unwrap();
+        h.state_where(|s| s.audio_device_list.iter().any(|d| d.id == "3-tv"))
+            .await;
+
+        h.fake().remove_device("1-speakers").unwrap();
+        let state = h
+            .state_where(|s| s.audio_device_list.iter().all(|d| d.id != "1-speakers"))
+            .await;
+        assert_eq!(state.default, "2-headphones");
+    }
+
+    #[tokio::test]
+    async fn restarts_a_backend_which_failed_to_start() {
+        let mut h = Harness::start_failing("restart", 2).await;
+
+        for (expected, delay) in [(1, 500), (2, 1000)] {
+            let status = timeout(WAIT, h.ret.status_rx.recv()).await.unwrap();
+            assert!(matches!(
+                status,
+                Some(BackendStatus::Restarting { attempt, delay_ms, reason })
+                    if attempt == expected
+                        && delay_ms == delay
+                        && reason == APIError::AudioServiceUnavailable.to_string()
+            ));
+        }
+        let status = timeout(WAIT, h.ret.status_rx.recv()).await.unwrap();
+        assert!(matches!(
+            status,
+            Some(BackendStatus::Recovered { attempts: 2 })
+        ));
+
+        h.state_where(|s| s.default == "1-speakers").await;
+        let result = h
+            .query(IPCHandlers::MuteStateChange {
+                id: "1-speakers".to_string(),
+                muted: true,
+            })
+            .await;
+        assert!(matches!(result, Ok(QueryResult::Muted { muted: true, .. })));
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    audio::{
+        notifier::{Notification, DEVICE_STATE_ACTIVE},
+        AudioEndpoint,
+    },
+    preferences::DevicePreferences,
+    profile::DeviceLevel,
+    sender::AudioDeviceMap,
+    store,
+};
+
+const VOLUMES_FILE: &str = "volumes.json";
+
+/// Last known volume and mute state per device id, put back when the device reconnects.
+///
+/// Bluetooth and USB devices tend to come back at full volume otherwise.
+pub struct VolumeMemory {
+    path: PathBuf,
+    levels: BTreeMap<String, DeviceLevel>,
+}
+
+impl VolumeMemory {
+    pub fn open(config_dir: &Path) -> Self {
+        let path = config_dir.join(VOLUMES_FILE);
+        let levels = store::load(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load {:?}: {:?}", path, e);
+            BTreeMap::new()
+        });
+
+        Self { path, levels }
+    }
+
+    /// Restores devices which reconnected in this batch, then records the volumes which changed.
+    pub fn on_notifications<E: AudioEndpoint>(
+        &mut self,
+        audio_dict: &AudioDeviceMap<E>,
+        preferences: &DevicePreferences,
+        notifications: &[Notification],
+    ) {
+        let mut connected = Vec::new();
+        for notification in notifications {
+            match notification {
+                Notification::DeviceAdded { id } => connected.push(id),
+                Notification::DeviceStateChanged { id, state } if *state == DEVICE_STATE_ACTIVE => {
+                    connected.push(id)
+                }
+                _ => {}
+            }
+        }
+
+        for id in connected.iter() {
+            let (Some(audio), Some(level)) = (audio_dict.get(*id), self.levels.get(*id)) else {
+                continue;
+            };
+
+            // the limit may have been lowered while the device was away
+            if let Err(e) = audio.set_volume(preferences.limit_volume(id, level.volume)) {
+                log::error!("@audio.set_volume {:?}", e);
+            }
+            if let Err(e) = audio.set_mute_state(level.muted) {
+                log::error!("@audio.set_mute {:?}", e);
+            }
+        }
+
+        let mut changed = false;
+        for notification in notifications {
+            let Notification::VolumeChanged { id, volume, muted } = notification else {
+                continue;
+            };
+            // the level a device reconnects with is what gets replaced above, not what to keep
+            if connected.contains(&id) {
+                continue;
+            }
+
+            let level = DeviceLevel {
+                volume: *volume,
+                muted: *muted,
+            };
+            if self.levels.get(id) != Some(&level) {
+                self.levels.insert(id.clone(), level);
+                changed = true;
+            }
+        }
+
+        if changed {
+            if let Err(e) = store::save(&self.path, &self.levels) {
+                log::error!("Failed to save {:?}: {:?}", self.path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tokio::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+    use crate::ipc::{
+        audio::{
+            fake::{FakeBackend, FakeDeviceState},
+            DataFlow,
+        },
+        init::get_audio_dictionary,
+        preferences::DevicePreference,
+        settings::Settings,
+        testing,
+    };
+
+    fn headphones() -> FakeDeviceState {
+        FakeDeviceState::new("Headphones", DataFlow::Render)
+    }
+
+    fn backend() -> (Arc<FakeBackend>, Receiver<Notification>) {
+        let (tx, rx) = channel(256);
+        let is = FakeBackend::with_devices(
+            &tx,
+            [
+                (
+                    "1-speakers".to_string(),
+                    FakeDeviceState::new("Speakers", DataFlow::Render),
+                ),
+                ("2-headphones".to_string(), headphones()),
+            ],
+        );
+
+        (Arc::new(is), rx)
+    }
+
+    /// Hands what the backend notified so far to `memory` as one batch.
+    fn batch(
+        memory: &mut VolumeMemory,
+        is: &Arc<FakeBackend>,
+        rx: &mut Receiver<Notification>,
+        preferences: &DevicePreferences,
+    ) {
+        let mut notifications = Vec::new();
+        while let Ok(n) = rx.try_recv() {
+            notifications.push(n);
+        }
+
+        memory.on_notifications(
+            &get_audio_dictionary(is).unwrap(),
+            preferences,
+            &notifications,
+        );
+    }
+
+    /// Sets the volume of the headphones, then unplugs them and plugs them back in at full
+    /// volume, feeding each step to `memory`.
+    fn reconnect_at(
+        memory: &mut VolumeMemory,
+        is: &Arc<FakeBackend>,
+        rx: &mut Receiver<Notification>,
+        preferences: &DevicePreferences,
+        volume: f32,
+    ) {
+        get_audio_dictionary(is).unwrap()["2-headphones"]
+            .set_volume(volume)
+            .unwrap();
+        batch(memory, is, rx, preferences);
+
+        is.remove_device("2-headphones").unwrap();
+        batch(memory, is, rx, preferences);
+
+        is.add_device("2-headphones", headphones()).unwrap();
+        batch(memory, is, rx, preferences);
+    }
+
+    #[test]
+    fn restores_the_volume_of_a_reconnected_device() {
+        let (is, mut rx) = backend();
+        let mut memory = VolumeMemory::open(&testing::config_dir("memory-restore"));
+        let preferences = DevicePreferences::default();
+
+        reconnect_at(&mut memory, &is, &mut rx, &preferences, 0.35);
+
+        assert_eq!(is.device("2-headphones").unwrap().volume, 0.35);
+        assert_eq!(is.device("1-speakers").unwrap().volume, 1.0);
+    }
+
+    #[test]
+    fn remembers_volumes_across_restarts() {
+        let config_dir = testing::config_dir("memory-restart");
+        let (is, mut rx) = backend();
+        let preferences = DevicePreferences::default();
+
+        let mut memory = VolumeMemory::open(&config_dir);
+        get_audio_dictionary(&is).unwrap()["2-headphones"]
+            .set_volume(0.2)
+            .unwrap();
+        batch(&mut memory, &is, &mut rx, &preferences);
+        is.remove_device("2-headphones").unwrap();
+        batch(&mut memory, &is, &mut rx, &preferences);
+
+        let mut memory = VolumeMemory::open(&config_dir);
+        is.add_device("2-headphones", headphones()).unwrap();
+        batch(&mut memory, &is, &mut rx, &preferences);
+
+        assert_eq!(is.device("2-headphones").unwrap().volume, 0.2);
+    }
+
+    #[test]
+    fn restores_at_most_the_current_limit() {
+        let (is, mut rx) = backend();
+        let mut memory = VolumeMemory::open(&testing::config_dir("memory-limit"));
+        let limited = DevicePreference {
+            max_volume: Some(0.5),
+            ..DevicePreference::default()
+        };
+        let settings = Settings {
+            devices: [("2-headphones".to_string(), limited)].into(),
+            ..Settings::default()
+        };
+
+        reconnect_at(
+            &mut memory,
+            &is,
+            &mut rx,
+            &DevicePreferences::new(&settings).unwrap(),
+            0.8,
+        );
+
+        assert_eq!(is.device("2-headphones").unwrap().volume, 0.5);
+    }
+}
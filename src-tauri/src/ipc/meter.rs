@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Result};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::{
+        mpsc::{error::TrySendError, Sender},
+        watch,
+    },
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+
+use super::{
+    audio::{AudioEndpoint, PeakLevels},
+    sender::AudioDeviceMap,
+};
+
+/// Peak levels sampled at the same time, keyed by device id.
+///
+/// Devices whose backend cannot meter are left out.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct MeterPayload {
+    pub peaks: BTreeMap<String, PeakLevels>,
+}
+
+/// Time between samples at `rate` samples per second.
+pub fn sample_interval(rate: u32) -> Duration {
+    Duration::from_secs(1) / rate.max(1)
+}
+
+fn sample<E: AudioEndpoint>(audio_dict: &Mutex<AudioDeviceMap<E>>) -> Result<MeterPayload> {
+    let dict = audio_dict
+        .lock()
+        .map_err(|_| anyhow!("audio_dict is poisoned"))?;
+
+    let peaks = dict
+        .iter()
+        .filter_map(|(id, audio)| Some((id.clone(), audio.get_peak_levels().ok()?)))
+        .collect();
+
+    Ok(MeterPayload { peaks })
+}
+
+/// Samples every device while `interval_rx` holds an interval, and does nothing while it holds
+/// `None`, which is the case whenever the window is hidden.
+pub fn spawn<E: AudioEndpoint>(
+    audio_dict: Arc<Mutex<AudioDeviceMap<E>>>,
+    mut interval_rx: watch::Receiver<Option<Duration>>,
+    tx: Sender<MeterPayload>,
+) -> JoinHandle<Result<()>> {
+    tokio::spawn(async move {
+        loop {
+            let current = *interval_rx.borrow_and_update();
+            let Some(period) = current else {
+                if interval_rx.changed().await.is_err() {
+                    return Ok(());
+                }
+                continue;
+            };
+
+            let mut ticker = interval(period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        // a sample the window has no time for is dropped, not queued
+                        match tx.try_send(sample(&audio_dict)?) {
+                            Err(TrySendError::Closed(_)) => return Ok(()),
+                            Err(TrySendError::Full(_)) | Ok(()) => {}
+                        }
+                    }
+                    changed = interval_rx.changed() => {
+                        if changed.is_err() {
+                            return Ok(());
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
@@ -1,10 +1,25 @@
-use tauri::AppHandle;
+use tauri::{async_runtime::Sender, AppHandle, Manager};
 use tauri_plugin_window_state::{AppHandleExt, StateFlags};
 
+use init::{IPCHandlers, Request};
+
 pub mod audio;
+pub mod control;
 pub mod error;
 pub mod init;
+pub mod memory;
+pub mod meter;
+pub mod policy;
+pub mod preferences;
+pub mod profile;
+pub mod routing;
 pub mod sender;
+pub mod settings;
+pub mod state;
+pub mod store;
+pub mod supervisor;
+#[cfg(test)]
+mod testing;
 
 #[tauri::command]
 pub fn quit(app: AppHandle) {
@@ -15,3 +30,15 @@ pub fn quit(app: AppHandle) {
 
     app.exit(0)
 }
+
+/// Hands `query` to the backend from places outside the webview, such as the tray.
+///
+/// Nobody waits for the result, so failures only end up in the log.
+pub fn send_query(app: &AppHandle, query: IPCHandlers) {
+    let tx = app.state::<Sender<Request>>().inner().clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = tx.send(query.into()).await {
+            log::error!("{:?}", e);
+        }
+    });
+}
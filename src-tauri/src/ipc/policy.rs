@@ -0,0 +1,1113 @@
+use super::{
+    audio::{
+        notifier::{Notification, DEVICE_STATE_ACTIVE},
+        AudioBackend, AudioEndpoint, DataFlow, FormFactor, Role,
+    },
+    preferences::DevicePreferences,
+    sender::{AudioDeviceMap, Report},
+    settings::Settings,
+};
+
+/// Slack for the volume read back after setting it, so a pulled back volume is not pulled again.
+const VOLUME_EPSILON: f32 = 0.005;
+
+/// Picks the next device from the user's ranking when the default output goes away, instead
+/// of whatever Windows picks on its own.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FallbackSettings {
+    pub enabled: bool,
+    /// Mutes the device before it becomes the default.
+    pub mute: bool,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum FallbackReason {
+    /// The highest-ranked device left was made the default.
+    Ranked,
+    /// The highest-ranked device left was already picked by the system.
+    AlreadyDefault,
+    /// No ranked device is connected, so the system's pick was kept.
+    NoRankedDevice,
+}
+
+/// What happens to the new default output when the previous one is unplugged.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type")]
+pub enum SafeguardAction {
+    Mute,
+    /// Lowers the volume to `volume` (`0.0` to `1.0`) when it is above it.
+    Volume {
+        volume: f32,
+    },
+}
+
+/// Keeps audio from blasting through the speakers when the headphones are unplugged.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SafeguardSettings {
+    pub enabled: bool,
+    pub action: SafeguardAction,
+    /// Form factors of the removed default which trigger the safeguard, empty for any.
+    pub from: Vec<FormFactor>,
+    /// Form factors of the new default which are protected, empty for any.
+    pub to: Vec<FormFactor>,
+}
+
+impl Default for SafeguardSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            action: SafeguardAction::Mute,
+            from: vec![FormFactor::Headphones, FormFactor::Headset],
+            to: vec![FormFactor::Speakers],
+        }
+    }
+}
+
+impl SafeguardSettings {
+    fn matches(form_factors: &[FormFactor], form_factor: FormFactor) -> bool {
+        form_factors.is_empty() || form_factors.contains(&form_factor)
+    }
+}
+
+/// Makes a newly connected output the default when it ranks above the current default.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoSwitchSettings {
+    pub enabled: bool,
+    /// Roles the new device takes over.
+    pub roles: Vec<Role>,
+}
+
+impl Default for AutoSwitchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            roles: Role::ALL.to_vec(),
+        }
+    }
+}
+
+/// Position of `id` in the user's ranking, lower is preferred. Unranked devices come last.
+fn rank(settings: &Settings, id: &str) -> usize {
+    settings
+        .priority
+        .iter()
+        .position(|p| p == id)
+        .unwrap_or(usize::MAX)
+}
+
+/// Output devices which became available in this batch of notifications.
+fn connected_outputs<'a, E: AudioEndpoint>(
+    audio_dict: &'a AudioDeviceMap<E>,
+    notifications: &[Notification],
+) -> Vec<&'a E> {
+    let mut outputs = Vec::new();
+    for notification in notifications {
+        let id = match notification {
+            Notification::DeviceAdded { id } => id,
+            Notification::DeviceStateChanged { id, state } if *state == DEVICE_STATE_ACTIVE => id,
+            _ => continue,
+        };
+
+        let Some(audio) = audio_dict.get(id) else {
+            continue;
+        };
+        if audio.flow() == DataFlow::Render && !outputs.iter().any(|a: &&E| a.id() == id) {
+            outputs.push(audio);
+        }
+    }
+
+    outputs
+}
+
+/// The default output as of the last batch, to tell what was lost when it goes away.
+#[derive(Debug, Clone)]
+struct DefaultOutput {
+    id: String,
+    form_factor: FormFactor,
+}
+
+/// Automatic reactions to devices coming and going.
+#[derive(Debug, Default)]
+pub struct Policy {
+    default: Option<DefaultOutput>,
+    /// The default output which went away, until the safeguard has seen what replaced it.
+    ///
+    /// The system may pick the new default a batch after the removal.
+    unplugged: Option<DefaultOutput>,
+}
+
+impl Policy {
+    /// Reacts before the devices are enumerated again, which takes a while, so the new default
+    /// is safeguarded as soon as possible. It is usually connected already, and the rest waits
+    /// for `on_notifications`.
+    pub fn on_notifications_early<B: AudioBackend>(
+        &mut self,
+        is: &B,
+        audio_dict: &AudioDeviceMap<B::Endpoint>,
+        settings: &Settings,
+        notifications: &[Notification],
+    ) -> Vec<Report> {
+        self.note_unplugged(settings, notifications);
+
+        self.safeguard(is, audio_dict, settings)
+            .into_iter()
+            .collect()
+    }
+
+    /// Reacts to a batch of notifications once the devices have been enumerated again, and
+    /// returns what it did so the UI can tell the user.
+    pub fn on_notifications<B: AudioBackend>(
+        &mut self,
+        is: &B,
+        audio_dict: &AudioDeviceMap<B::Endpoint>,
+        settings: &Settings,
+        preferences: &DevicePreferences,
+        notifications: &[Notification],
+    ) -> Vec<Report> {
+        let mut reports = limit_volumes(audio_dict, preferences, notifications);
+
+        if let Some(report) = self.fallback(is, audio_dict, settings, notifications) {
+            reports.push(report);
+        }
+        // after the fallback, so it sees the device which ends up as the default
+        if let Some(report) = self.safeguard(is, audio_dict, settings) {
+            reports.push(report);
+        }
+        if let Some(report) = auto_switch(is, audio_dict, settings, notifications) {
+            reports.push(report);
+        }
+
+        self.remember_default(is, audio_dict);
+
+        reports
+    }
+
+    pub fn remember_default<B: AudioBackend>(
+        &mut self,
+        is: &B,
+        audio_dict: &AudioDeviceMap<B::Endpoint>,
+    ) {
+        self.default = is
+            .get_default_audio_id(DataFlow::Render, Role::Multimedia)
+            .ok()
+            .map(|id| DefaultOutput {
+                form_factor: audio_dict
+                    .get(&id)
+                    .map(|a| a.form_factor())
+                    .unwrap_or_default(),
+                id,
+            });
+
+        // once the system moved on without the safeguard, later changes have nothing to do with it
+        let current = self.default.as_ref().map(|d| d.id.as_str());
+        if self
+            .unplugged
+            .as_ref()
+            .is_some_and(|u| Some(u.id.as_str()) != current)
+        {
+            self.unplugged = None;
+        }
+    }
+
+    /// Remembers the default output when this batch removes it, and forgets it when it comes
+    /// back.
+    fn note_unplugged(&mut self, settings: &Settings, notifications: &[Notification]) {
+        if !settings.safeguard.enabled {
+            self.unplugged = None;
+            return;
+        }
+        let Some(previous) = &self.default else {
+            return;
+        };
+
+        for notification in notifications {
+            match notification {
+                Notification::DeviceRemoved { id } if *id == previous.id => {
+                    self.unplugged = Some(previous.clone());
+                }
+                Notification::DeviceStateChanged { id, state } if *id == previous.id => {
+                    self.unplugged = (*state != DEVICE_STATE_ACTIVE).then(|| previous.clone());
+                }
+                Notification::DeviceAdded { id } if *id == previous.id => {
+                    self.unplugged = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the output which was the default before this batch has gone away.
+    fn removed_default<E: AudioEndpoint>(
+        &self,
+        audio_dict: &AudioDeviceMap<E>,
+        notifications: &[Notification],
+    ) -> Option<&DefaultOutput> {
+        let previous = self.default.as_ref()?;
+
+        let removed = notifications.iter().any(|n| match n {
+            Notification::DeviceRemoved { id } => *id == previous.id,
+            Notification::DeviceStateChanged { id, state } => {
+                *id == previous.id && *state != DEVICE_STATE_ACTIVE
+            }
+            _ => false,
+        });
+
+        // it may have come back within the same batch
+        (removed && !audio_dict.contains_key(&previous.id)).then_some(previous)
+    }
+
+    fn fallback<B: AudioBackend>(
+        &self,
+        is: &B,
+        audio_dict: &AudioDeviceMap<B::Endpoint>,
+        settings: &Settings,
+        notifications: &[Notification],
+    ) -> Option<Report> {
+        if !settings.fallback.enabled {
+            return None;
+        }
+        let removed = self.removed_default(audio_dict, notifications)?.id.clone();
+
+        let current = is
+            .get_default_audio_id(DataFlow::Render, Role::Multimedia)
+            .ok();
+        let candidate = audio_dict
+            .values()
+            .filter(|a| a.flow() == DataFlow::Render && rank(settings, a.id()) != usize::MAX)
+            .min_by_key(|a| rank(settings, a.id()));
+
+        let Some(candidate) = candidate else {
+            return Some(Report::FallbackSelected {
+                removed,
+                id: current,
+                muted: false,
+                reason: FallbackReason::NoRankedDevice,
+            });
+        };
+
+        let muted = settings.fallback.mute;
+        if muted {
+            if let Err(e) = candidate.set_mute_state(true) {
+                log::error!("@audio.set_mute {:?}", e);
+            }
+        }
+
+        let reason = if current.as_deref() == Some(candidate.id()) {
+            FallbackReason::AlreadyDefault
+        } else {
+            if let Err(e) = candidate.set_as_default(&Role::ALL) {
+                log::error!("@audio.set_as_default {:?}", e);
+                return None;
+            }
+            FallbackReason::Ranked
+        };
+
+        Some(Report::FallbackSelected {
+            removed,
+            id: Some(candidate.id().to_string()),
+            muted,
+            reason,
+        })
+    }
+
+    fn safeguard<B: AudioBackend>(
+        &mut self,
+        is: &B,
+        audio_dict: &AudioDeviceMap<B::Endpoint>,
+        settings: &Settings,
+    ) -> Option<Report> {
+        let unplugged = self.unplugged.as_ref()?;
+        let id = is
+            .get_default_audio_id(DataFlow::Render, Role::Multimedia)
+            .ok()?;
+        // the system has not picked another default yet
+        if id == unplugged.id {
+            return None;
+        }
+        // a device connected in this batch is only known after enumerating again
+        let audio = audio_dict.get(&id)?;
+        let removed = self.unplugged.take()?;
+
+        let safeguard = &settings.safeguard;
+        if !SafeguardSettings::matches(&safeguard.from, removed.form_factor)
+            || !SafeguardSettings::matches(&safeguard.to, audio.form_factor())
+        {
+            return None;
+        }
+
+        let e = match safeguard.action {
+            SafeguardAction::Mute => {
+                if audio.get_mute_state().unwrap_or(false) {
+                    return None;
+                }
+                audio.set_mute_state(true)
+            }
+            SafeguardAction::Volume { volume } => {
+                if audio.get_volume().is_ok_and(|current| current <= volume) {
+                    return None;
+                }
+                audio.set_volume(volume)
+            }
+        };
+        if let Err(e) = e {
+            log::error!("@safeguard {:?}", e);
+            return None;
+        }
+
+        Some(Report::Safeguarded {
+            id,
+            removed: removed.id,
+            action: safeguard.action,
+        })
+    }
+}
+
+fn auto_switch<B: AudioBackend>(
+    is: &B,
+    audio_dict: &AudioDeviceMap<B::Endpoint>,
+    settings: &Settings,
+    notifications: &[Notification],
+) -> Option<Report> {
+    if !settings.auto_switch.enabled || settings.auto_switch.roles.is_empty() {
+        return None;
+    }
+
+    let candidate = connected_outputs(audio_dict, notifications)
+        .into_iter()
+        .min_by_key(|a| rank(settings, a.id()))?;
+
+    let current = is
+        .get_default_audio_id(DataFlow::Render, Role::Multimedia)
+        .ok();
+    let current_rank = current
+        .as_deref()
+        .map_or(usize::MAX, |id| rank(settings, id));
+    if rank(settings, candidate.id()) >= current_rank {
+        return None;
+    }
+
+    let roles = settings.auto_switch.roles.clone();
+    if let Err(e) = candidate.set_as_default(&roles) {
+        log::error!("@audio.set_as_default {:?}", e);
+        return None;
+    }
+
+    Some(Report::AutoSwitched {
+        id: candidate.id().to_string(),
+        previous: current,
+        roles,
+    })
+}
+
+/// Pulls devices back to their limit when something else, like media keys or another app,
+/// raised the volume above it, or when they connect above it.
+fn limit_volumes<E: AudioEndpoint>(
+    audio_dict: &AudioDeviceMap<E>,
+    preferences: &DevicePreferences,
+    notifications: &[Notification],
+) -> Vec<Report> {
+    let mut ids = notifications
+        .iter()
+        .filter_map(|n| match n {
+            Notification::VolumeChanged { id, .. } | Notification::DeviceAdded { id } => Some(id),
+            Notification::DeviceStateChanged { id, state } if *state == DEVICE_STATE_ACTIVE => {
+                Some(id)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+
+    limit(audio_dict, preferences, ids)
+}
+
+/// Pulls every device back to its limit, for when the backend starts or the limits change.
+pub fn limit_all_volumes<E: AudioEndpoint>(
+    audio_dict: &AudioDeviceMap<E>,
+    preferences: &DevicePreferences,
+) -> Vec<Report> {
+    limit(audio_dict, preferences, audio_dict.keys())
+}
+
+fn limit<'a, E: AudioEndpoint>(
+    audio_dict: &AudioDeviceMap<E>,
+    preferences: &DevicePreferences,
+    ids: impl IntoIterator<Item = &'a String>,
+) -> Vec<Report> {
+    let mut reports = Vec::new();
+    for id in ids {
+        let Some(audio) = audio_dict.get(id) else {
+            continue;
+        };
+        let max_volume = preferences.max_volume(id);
+        // the notification may be stale by now, so the current volume is checked instead
+        let Ok(volume) = audio.get_volume() else {
+            continue;
+        };
+        if volume <= max_volume + VOLUME_EPSILON {
+            continue;
+        }
+
+        if let Err(e) = audio.set_volume(max_volume) {
+            log::error!("@audio.set_volume {:?}", e);
+            continue;
+        }
+        reports.push(Report::VolumeLimited {
+            id: id.clone(),
+            volume,
+            max_volume,
+        });
+    }
+
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tokio::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+    use crate::ipc::{
+        audio::fake::{FakeBackend, FakeDeviceState, FakeEndpoint},
+        init::get_audio_dictionary,
+        preferences::DevicePreference,
+    };
+
+    fn output(form_factor: FormFactor) -> FakeDeviceState {
+        let mut device = FakeDeviceState::new("output", DataFlow::Render);
+        device.form_factor = form_factor;
+        device
+    }
+
+    /// A policy over a fake backend, fed batches the way the backend loop feeds them.
+    struct Harness {
+        is: Arc<FakeBackend>,
+        rx: Receiver<Notification>,
+        policy: Policy,
+        settings: Settings,
+    }
+
+    impl Harness {
+        /// The first device by id starts as the default output.
+        fn new(devices: Vec<(&str, FakeDeviceState)>, settings: Settings) -> Self {
+            let (tx, rx) = channel(256);
+            let devices = devices.into_iter().map(|(id, d)| (id.to_string(), d));
+            let is = Arc::new(FakeBackend::with_devices(&tx, devices));
+
+            let mut policy = Policy::default();
+            policy.remember_default(&*is, &get_audio_dictionary(&is).unwrap());
+
+            Self {
+                is,
+                rx,
+                policy,
+                settings,
+            }
+        }
+
+        fn dict(&self) -> AudioDeviceMap<FakeEndpoint> {
+            get_audio_dictionary(&self.is).unwrap()
+        }
+
+        fn drain(&mut self) -> Vec<Notification> {
+            let mut notifications = Vec::new();
+            while let Ok(n) = self.rx.try_recv() {
+                notifications.push(n);
+            }
+            notifications
+        }
+
+        /// Hands `notifications` to the policy as one batch, `before` being the devices
+        /// enumerated for the previous one.
+        fn batch(
+            &mut self,
+            before: &AudioDeviceMap<FakeEndpoint>,
+            notifications: &[Notification],
+        ) -> Vec<Report> {
+            let is = &*self.is;
+            let mut reports =
+                self.policy
+                    .on_notifications_early(is, before, &self.settings, notifications);
+            reports.extend(self.policy.on_notifications(
+                is,
+                &get_audio_dictionary(&self.is).unwrap(),
+                &self.settings,
+                &DevicePreferences::new(&self.settings).unwrap(),
+                notifications,
+            ));
+            reports
+        }
+
+        /// Runs `f` on the backend and hands what it notified to the policy as one batch.
+        fn run(&mut self, f: impl FnOnce(&FakeBackend)) -> Vec<Report> {
+            // what earlier reactions notified was handled with them
+            self.drain();
+            let before = self.dict();
+            f(&*self.is);
+            let notifications = self.drain();
+            self.batch(&before, &notifications)
+        }
+
+        fn device(&self, id: &str) -> FakeDeviceState {
+            self.is.device(id).unwrap()
+        }
+
+        fn default(&self) -> String {
+            self.is
+                .get_default_audio_id(DataFlow::Render, Role::Multimedia)
+                .unwrap()
+        }
+    }
+
+    fn safeguard(action: SafeguardAction) -> Settings {
+        Settings {
+            safeguard: SafeguardSettings {
+                enabled: true,
+                action,
+                ..SafeguardSettings::default()
+            },
+            ..Settings::default()
+        }
+    }
+
+    fn headphones_and_speakers(settings: Settings) -> Harness {
+        Harness::new(
+            vec![
+                ("1-headphones", output(FormFactor::Headphones)),
+                ("2-speakers", output(FormFactor::Speakers)),
+            ],
+            settings,
+        )
+    }
+
+    #[test]
+    fn safeguard_mutes_speakers_when_headphones_are_unplugged() {
+        let mut h = headphones_and_speakers(safeguard(SafeguardAction::Mute));
+
+        let reports = h.run(|is| is.remove_device("1-headphones").unwrap());
+
+        assert_eq!(h.default(), "2-speakers");
+        assert!(h.device("2-speakers").muted);
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::Safeguarded { id, removed, action: SafeguardAction::Mute }]
+                if id == "2-speakers" && removed == "1-headphones"
+        ));
+    }
+
+    #[test]
+    fn safeguard_lowers_the_volume_only_when_above_it() {
+        let action = SafeguardAction::Volume { volume: 0.2 };
+
+        let mut h = headphones_and_speakers(safeguard(action));
+        let reports = h.run(|is| is.remove_device("1-headphones").unwrap());
+        assert_eq!(h.device("2-speakers").volume, 0.2);
+        assert!(!h.device("2-speakers").muted);
+        assert!(matches!(reports.as_slice(), [Report::Safeguarded { .. }]));
+
+        let mut speakers = output(FormFactor::Speakers);
+        speakers.volume = 0.1;
+        let mut h = Harness::new(
+            vec![
+                ("1-headphones", output(FormFactor::Headphones)),
+                ("2-speakers", speakers),
+            ],
+            safeguard(action),
+        );
+        let reports = h.run(|is| is.remove_device("1-headphones").unwrap());
+        assert_eq!(h.device("2-speakers").volume, 0.1);
+        assert!(reports.is_empty());
+    }
+
+    #[test]
+    fn safeguard_leaves_a_muted_default_alone() {
+        let mut speakers = output(FormFactor::Speakers);
+        speakers.muted = true;
+        let mut h = Harness::new(
+            vec![
+                ("1-headphones", output(FormFactor::Headphones)),
+                ("2-speakers", speakers),
+            ],
+            safeguard(SafeguardAction::Mute),
+        );
+
+        let reports = h.run(|is| is.remove_device("1-headphones").unwrap());
+
+        assert!(reports.is_empty());
+    }
+
+    #[test]
+    fn safeguard_waits_for_the_system_to_pick_a_new_default() {
+        let mut h = headphones_and_speakers(safeguard(SafeguardAction::Mute));
+
+        // the removal arrives before the system moved the default
+        let before = h.dict();
+        let removed = [Notification::DeviceRemoved {
+            id: "1-headphones".to_string(),
+        }];
+        assert!(h.batch(&before, &removed).is_empty());
+        assert!(!h.device("2-speakers").muted);
+
+        let reports = h.run(|is| is.remove_device("1-headphones").unwrap());
+        assert!(h.device("2-speakers").muted);
+        assert!(matches!(reports.as_slice(), [Report::Safeguarded { .. }]));
+    }
+
+    #[test]
+    fn safeguard_acts_before_enumerating_again() {
+        let mut h = headphones_and_speakers(safeguard(SafeguardAction::Mute));
+        let before = h.dict();
+        h.is.remove_device("1-headphones").unwrap();
+        let notifications = h.drain();
+
+        let reports = h
+            .policy
+            .on_notifications_early(&*h.is, &before, &h.settings, &notifications);
+
+        assert!(h.device("2-speakers").muted);
+        assert!(matches!(reports.as_slice(), [Report::Safeguarded { .. }]));
+    }
+
+    #[test]
+    fn safeguard_only_triggers_for_listed_form_factors() {
+        let devices = || {
+            vec![
+                ("1-speakers", output(FormFactor::Speakers)),
+                ("2-speakers", output(FormFactor::Speakers)),
+            ]
+        };
+
+        let mut h = Harness::new(devices(), safeguard(SafeguardAction::Mute));
+        let reports = h.run(|is| is.remove_device("1-speakers").unwrap());
+        assert!(reports.is_empty());
+        assert!(!h.device("2-speakers").muted);
+
+        // an empty list matches any form factor
+        let mut settings = safeguard(SafeguardAction::Mute);
+        settings.safeguard.from = Vec::new();
+        let mut h = Harness::new(devices(), settings);
+        let reports = h.run(|is| is.remove_device("1-speakers").unwrap());
+        assert!(matches!(reports.as_slice(), [Report::Safeguarded { .. }]));
+        assert!(h.device("2-speakers").muted);
+    }
+
+    #[test]
+    fn safeguard_only_protects_listed_form_factors() {
+        let devices = || {
+            vec![
+                ("1-headphones", output(FormFactor::Headphones)),
+                ("2-headset", output(FormFactor::Headset)),
+            ]
+        };
+
+        let mut h = Harness::new(devices(), safeguard(SafeguardAction::Mute));
+        let reports = h.run(|is| is.remove_device("1-headphones").unwrap());
+        assert!(reports.is_empty());
+        assert!(!h.device("2-headset").muted);
+
+        let mut settings = safeguard(SafeguardAction::Mute);
+        settings.safeguard.to = Vec::new();
+        let mut h = Harness::new(devices(), settings);
+        let reports = h.run(|is| is.remove_device("1-headphones").unwrap());
+        assert!(matches!(reports.as_slice(), [Report::Safeguarded { .. }]));
+        assert!(h.device("2-headset").muted);
+    }
+
+    #[test]
+    fn safeguard_does_nothing_when_disabled() {
+        let mut h = headphones_and_speakers(Settings::default());
+
+        let reports = h.run(|is| is.remove_device("1-headphones").unwrap());
+
+        assert!(reports.is_empty());
+        assert!(!h.device("2-speakers").muted);
+    }
+
+    #[test]
+    fn safeguard_ignores_outputs_which_were_not_the_default() {
+        let mut h = Harness::new(
+            vec![
+                ("1-speakers", output(FormFactor::Speakers)),
+                ("2-headphones", output(FormFactor::Headphones)),
+            ],
+            safeguard(SafeguardAction::Mute),
+        );
+
+        let reports = h.run(|is| is.remove_device("2-headphones").unwrap());
+
+        assert!(reports.is_empty());
+        assert!(!h.device("1-speakers").muted);
+    }
+
+    #[test]
+    fn safeguard_acts_once_per_unplug() {
+        let mut h = Harness::new(
+            vec![
+                ("1-headphones", output(FormFactor::Headphones)),
+                ("2-speakers", output(FormFactor::Speakers)),
+                ("3-speakers", output(FormFactor::Speakers)),
+            ],
+            safeguard(SafeguardAction::Mute),
+        );
+        h.run(|is| is.remove_device("1-headphones").unwrap());
+        assert!(h.device("2-speakers").muted);
+
+        let dict = h.dict();
+        let reports = h.run(|_| dict["3-speakers"].set_as_default(&Role::ALL).unwrap());
+
+        assert!(reports.is_empty());
+        assert!(!h.device("3-speakers").muted);
+    }
+
+    fn auto_switch_settings(priority: &[&str]) -> Settings {
+        Settings {
+            priority: priority.iter().map(|id| id.to_string()).collect(),
+            auto_switch: AutoSwitchSettings {
+                enabled: true,
+                ..AutoSwitchSettings::default()
+            },
+            ..Settings::default()
+        }
+    }
+
+    fn speakers(settings: Settings) -> Harness {
+        Harness::new(vec![("1-speakers", output(FormFactor::Speakers))], settings)
+    }
+
+    #[test]
+    fn auto_switch_prefers_a_higher_ranked_output() {
+        let mut h = speakers(auto_switch_settings(&["2-headphones", "1-speakers"]));
+
+        let reports = h.run(|is| {
+            is.add_device("2-headphones", output(FormFactor::Headphones))
+                .unwrap()
+        });
+
+        assert_eq!(h.default(), "2-headphones");
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::AutoSwitched { id, previous: Some(previous), .. }]
+                if id == "2-headphones" && previous == "1-speakers"
+        ));
+    }
+
+    #[test]
+    fn auto_switch_keeps_a_higher_ranked_default() {
+        let mut h = speakers(auto_switch_settings(&["1-speakers", "2-headphones"]));
+
+        let reports = h.run(|is| {
+            is.add_device("2-headphones", output(FormFactor::Headphones))
+                .unwrap()
+        });
+
+        assert_eq!(h.default(), "1-speakers");
+        assert!(reports.is_empty());
+    }
+
+    #[test]
+    fn auto_switch_ignores_unranked_outputs() {
+        // ranked default
+        let mut h = speakers(auto_switch_settings(&["1-speakers"]));
+        let reports = h.run(|is| {
+            is.add_device("2-headphones", output(FormFactor::Headphones))
+                .unwrap()
+        });
+        assert_eq!(h.default(), "1-speakers");
+        assert!(reports.is_empty());
+
+        // unranked default, tied with the new output
+        let mut h = speakers(auto_switch_settings(&[]));
+        let reports = h.run(|is| {
+            is.add_device("2-headphones", output(FormFactor::Headphones))
+                .unwrap()
+        });
+        assert_eq!(h.default(), "1-speakers");
+        assert!(reports.is_empty());
+    }
+
+    #[test]
+    fn auto_switch_leaves_an_unranked_default_for_a_ranked_output() {
+        let mut h = speakers(auto_switch_settings(&["2-headphones"]));
+
+        let reports = h.run(|is| {
+            is.add_device("2-headphones", output(FormFactor::Headphones))
+                .unwrap()
+        });
+
+        assert_eq!(h.default(), "2-headphones");
+        assert!(matches!(reports.as_slice(), [Report::AutoSwitched { .. }]));
+    }
+
+    #[test]
+    fn auto_switch_picks_the_best_of_a_batch() {
+        let mut h = speakers(auto_switch_settings(&[
+            "3-headset",
+            "2-headphones",
+            "1-speakers",
+        ]));
+
+        let reports = h.run(|is| {
+            is.add_device("2-headphones", output(FormFactor::Headphones))
+                .unwrap();
+            is.add_device("3-headset", output(FormFactor::Headset))
+                .unwrap();
+        });
+
+        assert_eq!(h.default(), "3-headset");
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::AutoSwitched { id, .. }] if id == "3-headset"
+        ));
+    }
+
+    #[test]
+    fn auto_switch_takes_over_the_configured_roles_only() {
+        let mut settings = auto_switch_settings(&["2-headphones"]);
+        settings.auto_switch.roles = vec![Role::Communications];
+        let mut h = speakers(settings);
+
+        h.run(|is| {
+            is.add_device("2-headphones", output(FormFactor::Headphones))
+                .unwrap()
+        });
+
+        let default = |role| h.is.get_default_audio_id(DataFlow::Render, role).unwrap();
+        assert_eq!(default(Role::Communications), "2-headphones");
+        assert_eq!(default(Role::Multimedia), "1-speakers");
+    }
+
+    #[test]
+    fn auto_switch_does_nothing_when_disabled() {
+        let mut settings = auto_switch_settings(&["2-headphones"]);
+        settings.auto_switch.enabled = false;
+        let mut h = speakers(settings);
+
+        let reports = h.run(|is| {
+            is.add_device("2-headphones", output(FormFactor::Headphones))
+                .unwrap()
+        });
+
+        assert_eq!(h.default(), "1-speakers");
+        assert!(reports.is_empty());
+    }
+
+    fn fallback_settings(priority: &[&str]) -> Settings {
+        Settings {
+            priority: priority.iter().map(|id| id.to_string()).collect(),
+            fallback: FallbackSettings {
+                enabled: true,
+                mute: false,
+            },
+            ..Settings::default()
+        }
+    }
+
+    /// The system picks `2-b` when `1-a` goes away, as it comes first.
+    fn three_outputs(settings: Settings) -> Harness {
+        Harness::new(
+            vec![
+                ("1-a", output(FormFactor::Speakers)),
+                ("2-b", output(FormFactor::Speakers)),
+                ("3-c", output(FormFactor::Speakers)),
+            ],
+            settings,
+        )
+    }
+
+    #[test]
+    fn fallback_picks_the_highest_ranked_output() {
+        let mut h = three_outputs(fallback_settings(&["3-c", "2-b"]));
+
+        let reports = h.run(|is| is.remove_device("1-a").unwrap());
+
+        assert_eq!(h.default(), "3-c");
+        assert!(!h.device("3-c").muted);
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::FallbackSelected {
+                removed,
+                id: Some(id),
+                muted: false,
+                reason: FallbackReason::Ranked,
+            }] if removed == "1-a" && id == "3-c"
+        ));
+    }
+
+    #[test]
+    fn fallback_skips_unranked_outputs() {
+        // 2-b comes first, but only 3-c is ranked
+        let mut h = three_outputs(fallback_settings(&["1-a", "3-c"]));
+
+        let reports = h.run(|is| is.remove_device("1-a").unwrap());
+
+        assert_eq!(h.default(), "3-c");
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::FallbackSelected {
+                reason: FallbackReason::Ranked,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn fallback_reports_when_the_system_already_picked_it() {
+        let mut h = three_outputs(fallback_settings(&["2-b", "3-c"]));
+
+        let reports = h.run(|is| is.remove_device("1-a").unwrap());
+
+        assert_eq!(h.default(), "2-b");
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::FallbackSelected {
+                id: Some(id),
+                reason: FallbackReason::AlreadyDefault,
+                ..
+            }] if id == "2-b"
+        ));
+    }
+
+    #[test]
+    fn fallback_keeps_the_system_pick_without_a_ranked_output() {
+        // every output left is unranked, so they are all tied
+        let mut h = three_outputs(fallback_settings(&["1-a"]));
+
+        let reports = h.run(|is| is.remove_device("1-a").unwrap());
+
+        assert_eq!(h.default(), "2-b");
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::FallbackSelected {
+                id: Some(id),
+                muted: false,
+                reason: FallbackReason::NoRankedDevice,
+                ..
+            }] if id == "2-b"
+        ));
+
+        let mut h = speakers(fallback_settings(&["1-speakers"]));
+        let reports = h.run(|is| is.remove_device("1-speakers").unwrap());
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::FallbackSelected {
+                id: None,
+                reason: FallbackReason::NoRankedDevice,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn fallback_mutes_the_output_it_picks() {
+        let mut settings = fallback_settings(&["3-c"]);
+        settings.fallback.mute = true;
+        let mut h = three_outputs(settings);
+
+        let reports = h.run(|is| is.remove_device("1-a").unwrap());
+
+        assert!(h.device("3-c").muted);
+        assert!(!h.device("2-b").muted);
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::FallbackSelected { muted: true, .. }]
+        ));
+    }
+
+    #[test]
+    fn fallback_ignores_outputs_which_were_not_the_default() {
+        let mut h = three_outputs(fallback_settings(&["3-c"]));
+
+        let reports = h.run(|is| is.remove_device("2-b").unwrap());
+
+        assert_eq!(h.default(), "1-a");
+        assert!(reports.is_empty());
+    }
+
+    fn limit_settings(limits: &[(&str, f32)]) -> Settings {
+        let devices = limits
+            .iter()
+            .map(|(id, max_volume)| {
+                let preference = DevicePreference {
+                    max_volume: Some(*max_volume),
+                    ..DevicePreference::default()
+                };
+                (id.to_string(), preference)
+            })
+            .collect();
+
+        Settings {
+            devices,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn limit_pulls_back_a_device_connected_above_it() {
+        let mut h = speakers(limit_settings(&[("2-headphones", 0.4)]));
+
+        let reports = h.run(|is| {
+            is.add_device("2-headphones", output(FormFactor::Headphones))
+                .unwrap()
+        });
+
+        assert_eq!(h.device("2-headphones").volume, 0.4);
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::VolumeLimited { id, volume, max_volume }]
+                if id == "2-headphones" && *volume == 1.0 && *max_volume == 0.4
+        ));
+    }
+
+    #[test]
+    fn limit_pulls_back_a_volume_raised_above_it() {
+        let mut h = speakers(limit_settings(&[("1-speakers", 0.5)]));
+
+        let dict = h.dict();
+
+        let reports = h.run(|_| dict["1-speakers"].set_volume(0.3).unwrap());
+        assert!(reports.is_empty());
+
+        let reports = h.run(|_| dict["1-speakers"].set_volume(0.9).unwrap());
+        assert_eq!(h.device("1-speakers").volume, 0.5);
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::VolumeLimited { id, .. }] if id == "1-speakers"
+        ));
+    }
+
+    #[test]
+    fn limit_pulls_back_every_device_when_it_changes() {
+        let mut h = three_outputs(Settings::default());
+        h.dict()["2-b"].set_volume(0.8).unwrap();
+
+        h.settings = limit_settings(&[("1-a", 0.5), ("2-b", 0.6)]);
+        let reports = limit_all_volumes(&h.dict(), &DevicePreferences::new(&h.settings).unwrap());
+
+        assert_eq!(h.device("1-a").volume, 0.5);
+        assert_eq!(h.device("2-b").volume, 0.6);
+        // no limit was set for it
+        assert_eq!(h.device("3-c").volume, 1.0);
+        assert!(matches!(
+            reports.as_slice(),
+            [Report::VolumeLimited { id: a, .. }, Report::VolumeLimited { id: b, .. }]
+                if a == "1-a" && b == "2-b"
+        ));
+    }
+
+    #[test]
+    fn limit_leaves_devices_without_one_alone() {
+        let mut h = three_outputs(limit_settings(&[("1-a", 0.5)]));
+
+        let reports = h.run(|is| {
+            is.add_device("4-d", output(FormFactor::Speakers)).unwrap();
+        });
+
+        assert_eq!(h.device("4-d").volume, 1.0);
+        assert!(reports.is_empty());
+    }
+}
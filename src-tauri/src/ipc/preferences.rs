@@ -0,0 +1,197 @@
+use anyhow::Result;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+use super::{error::APIError, settings::Settings};
+
+/// How one device is shown, keyed by device id in the settings.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DevicePreference {
+    /// Shown instead of the name reported by the platform.
+    pub alias: Option<String>,
+    pub hidden: bool,
+    /// Devices with an order come first, lowest first. The others keep the platform order.
+    pub order: Option<i32>,
+    /// Highest volume allowed on the device, from `0.0` to `1.0`.
+    pub max_volume: Option<f32>,
+}
+
+/// Aliases, hiding and ordering from the settings, with the hide rules compiled.
+#[derive(Debug, Clone, Default)]
+pub struct DevicePreferences {
+    devices: BTreeMap<String, DevicePreference>,
+    hide_rules: Vec<Regex>,
+}
+
+impl DevicePreferences {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let hide_rules = settings
+            .hide_rules
+            .iter()
+            .map(|rule| {
+                Regex::new(rule).map_err(|e| {
+                    APIError::invalid_argument("hideRules", format!("{:?}: {}", rule, e)).into()
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for (id, device) in settings.devices.iter() {
+            if let Some(max_volume) = device.max_volume {
+                if !(0.0..=1.0).contains(&max_volume) {
+                    let reason = format!("maxVolume of {:?} must be in [0, 1]: {}", id, max_volume);
+                    return Err(APIError::invalid_argument("devices", reason).into());
+                }
+            }
+        }
+
+        Ok(Self {
+            devices: settings.devices.clone(),
+            hide_rules,
+        })
+    }
+
+    pub fn display_name(&self, id: &str, name: &str) -> String {
+        self.devices
+            .get(id)
+            .and_then(|d| d.alias.as_deref())
+            .filter(|alias| !alias.trim().is_empty())
+            .unwrap_or(name)
+            .to_string()
+    }
+
+    /// Hide rules match the name reported by the platform, not the alias.
+    pub fn is_hidden(&self, id: &str, name: &str) -> bool {
+        self.devices.get(id).is_some_and(|d| d.hidden)
+            || self.hide_rules.iter().any(|rule| rule.is_match(name))
+    }
+
+    pub fn order(&self, id: &str) -> i32 {
+        self.devices
+            .get(id)
+            .and_then(|d| d.order)
+            .unwrap_or(i32::MAX)
+    }
+
+    /// `1.0` unless the user set a limit.
+    pub fn max_volume(&self, id: &str) -> f32 {
+        self.devices
+            .get(id)
+            .and_then(|d| d.max_volume)
+            .unwrap_or(1.0)
+    }
+
+    /// Clamps a requested volume into what the device is allowed.
+    pub fn limit_volume(&self, id: &str, volume: f32) -> f32 {
+        volume.clamp(0.0, self.max_volume(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preferences(
+        devices: impl IntoIterator<Item = (&'static str, DevicePreference)>,
+        hide_rules: &[&str],
+    ) -> Result<DevicePreferences> {
+        let settings = Settings {
+            devices: devices
+                .into_iter()
+                .map(|(id, d)| (id.to_string(), d))
+                .collect(),
+            hide_rules: hide_rules.iter().map(|r| r.to_string()).collect(),
+            ..Settings::default()
+        };
+
+        DevicePreferences::new(&settings)
+    }
+
+    fn invalid_field(e: anyhow::Error) -> String {
+        match e.downcast::<APIError>() {
+            Ok(APIError::InvalidArgument { field, .. }) => field,
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn shows_aliases_instead_of_names() {
+        let alias = |alias: &str| DevicePreference {
+            alias: Some(alias.to_string()),
+            ..DevicePreference::default()
+        };
+        let p = preferences([("tv", alias("Living room")), ("dac", alias("  "))], &[]).unwrap();
+
+        assert_eq!(p.display_name("tv", "HDMI Output"), "Living room");
+        // a blank alias would leave the device without a visible name
+        assert_eq!(p.display_name("dac", "USB DAC"), "USB DAC");
+        assert_eq!(p.display_name("speakers", "Speakers"), "Speakers");
+    }
+
+    #[test]
+    fn hides_devices_by_flag_and_by_rule() {
+        let hidden = DevicePreference {
+            hidden: true,
+            ..DevicePreference::default()
+        };
+        let renamed = DevicePreference {
+            alias: Some("Monitor".to_string()),
+            ..DevicePreference::default()
+        };
+        let p = preferences(
+            [("spdif", hidden), ("nvidia", renamed)],
+            &["^NVIDIA", "(?i)virtual"],
+        )
+        .unwrap();
+
+        assert!(p.is_hidden("spdif", "Digital Output"));
+        assert!(p.is_hidden("cable", "Virtual Cable"));
+        // rules match the platform name even when an alias is set
+        assert!(p.is_hidden("nvidia", "NVIDIA High Definition Audio"));
+        assert!(!p.is_hidden("speakers", "Speakers"));
+    }
+
+    #[test]
+    fn rejects_an_invalid_hide_rule() {
+        let e = preferences([], &["(unclosed"]).unwrap_err();
+
+        assert_eq!(invalid_field(e), "hideRules");
+    }
+
+    #[test]
+    fn puts_ordered_devices_first() {
+        let order = |order| DevicePreference {
+            order: Some(order),
+            ..DevicePreference::default()
+        };
+        let p = preferences([("b", order(2)), ("c", order(-1))], &[]).unwrap();
+
+        // a stable sort keeps the platform order for the others
+        let mut ids = vec!["a", "b", "c", "d"];
+        ids.sort_by_key(|id| p.order(id));
+
+        assert_eq!(ids, ["c", "b", "a", "d"]);
+    }
+
+    #[test]
+    fn limits_volumes() {
+        let limited = DevicePreference {
+            max_volume: Some(0.5),
+            ..DevicePreference::default()
+        };
+        let p = preferences([("headphones", limited)], &[]).unwrap();
+
+        assert_eq!(p.max_volume("headphones"), 0.5);
+        assert_eq!(p.limit_volume("headphones", 0.8), 0.5);
+        assert_eq!(p.limit_volume("headphones", 0.3), 0.3);
+        assert_eq!(p.max_volume("speakers"), 1.0);
+        assert_eq!(p.limit_volume("speakers", 0.8), 0.8);
+
+        let invalid = DevicePreference {
+            max_volume: Some(1.5),
+            ..DevicePreference::default()
+        };
+        let e = preferences([("headphones", invalid)], &[]).unwrap_err();
+        assert_eq!(invalid_field(e), "devices");
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::Result;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    audio::{AudioBackend, AudioEndpoint, Role},
+    error::APIError,
+    preferences::DevicePreferences,
+    sender::{get_role_defaults, AudioDeviceMap, RoleDefaults},
+    store,
+};
+
+const PROFILES_FILE: &str = "profiles.json";
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DeviceLevel {
+    pub volume: f32,
+    pub muted: bool,
+}
+
+/// Default devices and levels captured from the devices connected at the time.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub role_defaults: RoleDefaults,
+    pub devices: BTreeMap<String, DeviceLevel>,
+}
+
+/// Outcome of applying a profile. Anything listed in `missing` or `errors` was not applied.
+#[derive(serde::Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileReport {
+    pub name: String,
+    /// Ids of devices the profile refers to which are not connected.
+    pub missing: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+impl ProfileReport {
+    pub fn is_partial(&self) -> bool {
+        !self.missing.is_empty() || !self.errors.is_empty()
+    }
+}
+
+/// Named profiles such as "meeting" or "late night", kept in the config dir.
+pub struct Profiles {
+    path: PathBuf,
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    pub fn open(config_dir: &Path) -> Self {
+        let path = config_dir.join(PROFILES_FILE);
+        let profiles = store::load(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load {:?}: {:?}", path, e);
+            BTreeMap::default()
+        });
+
+        Self { path, profiles }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    /// Stores the current defaults and levels under `name`, replacing any profile of that name.
+    pub fn save<B: AudioBackend>(
+        &mut self,
+        is: &B,
+        audio_dict: &AudioDeviceMap<B::Endpoint>,
+        name: &str,
+    ) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(APIError::invalid_argument("name", "Profile name is empty").into());
+        }
+
+        let devices = audio_dict
+            .iter()
+            .map(|(id, audio)| {
+                let level = DeviceLevel {
+                    volume: audio.get_volume()?,
+                    muted: audio.get_mute_state()?,
+                };
+                Ok((id.clone(), level))
+            })
+            .collect::<Result<_>>()?;
+
+        let profile = Profile {
+            role_defaults: get_role_defaults(is),
+            devices,
+        };
+        self.profiles.insert(name.to_string(), profile);
+
+        store::save(&self.path, &self.profiles)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        self.profiles
+            .remove(name)
+            .ok_or_else(|| no_such_profile(name))?;
+
+        store::save(&self.path, &self.profiles)
+    }
+
+    /// Applies as much of the profile as the connected devices allow.
+    ///
+    /// Only an unknown profile name is an error; missing devices end up in the report.
+    pub fn apply<E: AudioEndpoint>(
+        &self,
+        audio_dict: &AudioDeviceMap<E>,
+        preferences: &DevicePreferences,
+        name: &str,
+    ) -> Result<ProfileReport> {
+        let profile = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| no_such_profile(name))?;
+
+        let mut missing = BTreeSet::new();
+        let mut errors = Vec::new();
+
+        // one call per device, as setting a default notifies once per role anyway
+        let mut roles_by_device: BTreeMap<&String, Vec<Role>> = BTreeMap::new();
+        for (role, id) in profile.role_defaults.values().flatten() {
+            roles_by_device.entry(id).or_default().push(*role);
+        }
+
+        for (id, roles) in roles_by_device {
+            let Some(audio) = audio_dict.get(id) else {
+                missing.insert(id.clone());
+                continue;
+            };
+
+            if let Err(e) = audio.set_as_default(&roles) {
+                errors.push(format!("@audio.set_as_default {:?}", e));
+            }
+        }
+
+        for (id, level) in profile.devices.iter() {
+            let Some(audio) = audio_dict.get(id) else {
+                missing.insert(id.clone());
+                continue;
+            };
+
+            // the limit may have been lowered since the profile was saved
+            if let Err(e) = audio.set_volume(preferences.limit_volume(id, level.volume)) {
+                errors.push(format!("@audio.set_volume {:?}", e));
+            }
+            if let Err(e) = audio.set_mute_state(level.muted) {
+                errors.push(format!("@audio.set_mute {:?}", e));
+            }
+        }
+
+        Ok(ProfileReport {
+            name: name.to_string(),
+            missing: missing.into_iter().collect(),
+            errors,
+        })
+    }
+}
+
+fn no_such_profile(name: &str) -> anyhow::Error {
+    APIError::invalid_argument("name", format!("No such profile: {:?}", name)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+    use crate::ipc::{
+        audio::{
+            fake::{FakeBackend, FakeDeviceState},
+            DataFlow,
+        },
+        init::get_audio_dictionary,
+        testing,
+    };
+
+    fn backend() -> Arc<FakeBackend> {
+        let (tx, _) = channel(256);
+        let output = |name| FakeDeviceState::new(name, DataFlow::Render);
+        Arc::new(FakeBackend::with_devices(
+            &tx,
+            [
+                ("1-speakers".to_string(), output("Speakers")),
+                ("2-headphones".to_string(), output("Headphones")),
+                ("3-tv".to_string(), output("TV")),
+            ],
+        ))
+    }
+
+    #[test]
+    fn reports_what_could_not_be_applied() {
+        let is = backend();
+        let mut profiles = Profiles::open(&testing::config_dir("profile-partial"));
+        profiles
+            .save(&*is, &get_audio_dictionary(&is).unwrap(), "late night")
+            .unwrap();
+
+        let dict = get_audio_dictionary(&is).unwrap();
+        dict["1-speakers"].set_volume(0.3).unwrap();
+        is.remove_device("3-tv").unwrap();
+        let dict = get_audio_dictionary(&is).unwrap();
+        // unplugged after the devices were enumerated, so changing it fails
+        is.remove_device("2-headphones").unwrap();
+
+        let report = profiles
+            .apply(&dict, &DevicePreferences::default(), "late night")
+            .unwrap();
+
+        assert_eq!(report.name, "late night");
+        assert_eq!(report.missing, ["3-tv"]);
+        assert!(report.errors.iter().any(|e| e.contains("set_volume")));
+        assert!(report.errors.iter().any(|e| e.contains("set_mute")));
+        assert!(report.is_partial());
+
+        assert_eq!(is.device("1-speakers").unwrap().volume, 1.0);
+    }
+
+    #[test]
+    fn applies_a_whole_profile() {
+        let is = backend();
+        let mut profiles = Profiles::open(&testing::config_dir("profile-whole"));
+        let dict = get_audio_dictionary(&is).unwrap();
+        dict["2-headphones"].set_mute_state(true).unwrap();
+        profiles.save(&*is, &dict, "meeting").unwrap();
+
+        dict["2-headphones"].set_mute_state(false).unwrap();
+        let report = profiles
+            .apply(&dict, &DevicePreferences::default(), "meeting")
+            .unwrap();
+
+        assert!(!report.is_partial());
+        assert!(is.device("2-headphones").unwrap().muted);
+    }
+
+    #[test]
+    fn fails_on_an_unknown_profile() {
+        let is = backend();
+        let profiles = Profiles::open(&testing::config_dir("profile-unknown"));
+
+        let e = profiles
+            .apply(
+                &get_audio_dictionary(&is).unwrap(),
+                &DevicePreferences::default(),
+                "nope",
+            )
+            .unwrap_err();
+
+        assert!(matches!(
+            e.downcast_ref::<APIError>(),
+            Some(APIError::InvalidArgument { .. })
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+use anyhow::Result;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    audio::{AudioBackend, AudioEndpoint, DataFlow},
+    error::APIError,
+    sender::AudioDeviceMap,
+    store,
+};
+
+const APP_ROUTES_FILE: &str = "app_routes.json";
+
+/// Executable name (e.g. `chrome.exe`) to the id of the device it should play on.
+pub type AppRoutes = BTreeMap<String, String>;
+
+/// Per-application output devices, kept by executable name so they survive app restarts.
+pub struct AppRouting {
+    path: PathBuf,
+    routes: AppRoutes,
+
+    /// Processes already moved, so a route is applied once per process and device.
+    applied: HashMap<u32, String>,
+}
+
+impl AppRouting {
+    pub fn open(config_dir: &Path) -> Self {
+        let path = config_dir.join(APP_ROUTES_FILE);
+        let routes = store::load(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load {:?}: {:?}", path, e);
+            AppRoutes::default()
+        });
+
+        Self {
+            path,
+            routes,
+            applied: HashMap::new(),
+        }
+    }
+
+    pub fn routes(&self) -> &AppRoutes {
+        &self.routes
+    }
+
+    /// Stores the route of `app` and moves its running sessions right away.
+    ///
+    /// Only outputs can be routed to, as applications are moved by their render endpoint.
+    pub fn set_route<B: AudioBackend>(
+        &mut self,
+        is: &B,
+        audio_dict: &AudioDeviceMap<B::Endpoint>,
+        app: &str,
+        id: Option<String>,
+    ) -> Result<()> {
+        if let Some(id) = &id {
+            let audio = audio_dict
+                .get(id)
+                .ok_or_else(|| APIError::DeviceNotFound { id: id.clone() })?;
+            if audio.flow() != DataFlow::Render {
+                let reason = format!("{:?} is not an output device", id);
+                return Err(APIError::invalid_argument("id", reason).into());
+            }
+        }
+
+        for session in audio_dict
+            .values()
+            .flat_map(|a| a.get_sessions().unwrap_or_default())
+        {
+            if session.name == app {
+                is.set_app_default_device(session.pid, id.as_deref())?;
+                self.applied.remove(&session.pid);
+            }
+        }
+
+        match id {
+            Some(id) => self.routes.insert(app.to_string(), id),
+            None => self.routes.remove(app),
+        };
+
+        store::save(&self.path, &self.routes)
+    }
+
+    /// Moves sessions of routed applications which are not on their device yet.
+    pub fn enforce<B: AudioBackend>(&mut self, is: &B, audio_dict: &AudioDeviceMap<B::Endpoint>) {
+        let mut running = HashSet::new();
+        for (device_id, audio) in audio_dict {
+            if audio.flow() != DataFlow::Render {
+                continue;
+            }
+
+            let sessions = match audio.get_sessions() {
+                Ok(sessions) => sessions,
+                Err(e) => {
+                    log::error!("@audio.get_sessions {:?}", e);
+                    continue;
+                }
+            };
+
+            for session in sessions {
+                running.insert(session.pid);
+
+                let Some(target) = self.routes.get(&session.name) else {
+                    continue;
+                };
+                if target == device_id || self.applied.get(&session.pid) == Some(target) {
+                    continue;
+                }
+
+                match is.set_app_default_device(session.pid, Some(target)) {
+                    Ok(()) => {
+                        self.applied.insert(session.pid, target.clone());
+                    }
+                    Err(e) => log::error!("@set_app_default_device {:?}", e),
+                }
+            }
+        }
+
+        // process ids of exited processes may be reused by ones which were never moved
+        self.applied.retain(|pid, _| running.contains(pid));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+    use crate::ipc::{
+        audio::{
+            fake::{FakeBackend, FakeDeviceState},
+            AudioSession,
+        },
+        init::get_audio_dictionary,
+        testing,
+    };
+
+    fn session(pid: u32, name: &str) -> AudioSession {
+        AudioSession {
+            pid,
+            name: name.to_string(),
+            volume: 1.0,
+            muted: false,
+        }
+    }
+
+    fn backend() -> Arc<FakeBackend> {
+        let (tx, _) = channel(256);
+        Arc::new(FakeBackend::with_devices(
+            &tx,
+            [
+                (
+                    "1-speakers".to_string(),
+                    FakeDeviceState::new("Speakers", DataFlow::Render),
+                ),
+                (
+                    "2-headphones".to_string(),
+                    FakeDeviceState::new("Headphones", DataFlow::Render),
+                ),
+                (
+                    "3-mic".to_string(),
+                    FakeDeviceState::new("Mic", DataFlow::Capture),
+                ),
+            ],
+        ))
+    }
+
+    fn routed(name: &str, is: &Arc<FakeBackend>) -> AppRouting {
+        let mut routing = AppRouting::open(&testing::config_dir(name));
+        let dict = get_audio_dictionary(is).unwrap();
+        routing
+            .set_route(&**is, &dict, "game.exe", Some("2-headphones".to_string()))
+            .unwrap();
+        routing
+    }
+
+    #[test]
+    fn moves_new_sessions_of_the_routed_app() {
+        let is = backend();
+        let mut routing = routed("routing-new", &is);
+
+        is.add_session("1-speakers", session(10, "game.exe"))
+            .unwrap();
+        is.add_session("1-speakers", session(11, "browser.exe"))
+            .unwrap();
+        routing.enforce(&*is, &get_audio_dictionary(&is).unwrap());
+
+        assert_eq!(is.app_device(10).unwrap().as_deref(), Some("2-headphones"));
+        assert_eq!(is.app_device(11).unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_to_route_to_an_input() {
+        let is = backend();
+        let mut routing = AppRouting::open(&testing::config_dir("routing-input"));
+        let dict = get_audio_dictionary(&is).unwrap();
+
+        let e = routing
+            .set_route(&*is, &dict, "game.exe", Some("3-mic".to_string()))
+            .unwrap_err();
+
+        assert!(matches!(
+            e.downcast_ref::<APIError>(),
+            Some(APIError::InvalidArgument { .. })
+        ));
+        assert!(routing.routes().is_empty());
+    }
+
+    #[test]
+    fn leaves_sessions_on_inputs_alone() {
+        let is = backend();
+        let mut routing = routed("routing-capture", &is);
+
+        is.add_session("3-mic", session(10, "game.exe")).unwrap();
+        routing.enforce(&*is, &get_audio_dictionary(&is).unwrap());
+
+        assert_eq!(is.app_device(10).unwrap(), None);
+    }
+
+    #[test]
+    fn forgets_processes_which_exited() {
+        let is = backend();
+        let mut routing = routed("routing-exited", &is);
+
+        is.add_session("1-speakers", session(10, "game.exe"))
+            .unwrap();
+        routing.enforce(&*is, &get_audio_dictionary(&is).unwrap());
+        assert!(routing.applied.contains_key(&10));
+
+        is.remove_session("1-speakers", 10).unwrap();
+        routing.enforce(&*is, &get_audio_dictionary(&is).unwrap());
+        assert!(routing.applied.is_empty());
+
+        // a new process which reuses the id is moved as well
+        is.set_app_default_device(10, None).unwrap();
+        is.add_session("1-speakers", session(10, "game.exe"))
+            .unwrap();
+        routing.enforce(&*is, &get_audio_dictionary(&is).unwrap());
+        assert_eq!(is.app_device(10).unwrap().as_deref(), Some("2-headphones"));
+    }
+}
@@ -7,27 +7,57 @@ use anyhow::Result;
 use tokio::sync::mpsc::Sender;
 
 use super::{
-    audio::{notifier::Notification, IMMAudioDevice, Singleton},
+    audio::{
+        notifier::Notification, AudioBackend, AudioEndpoint, AudioSession, DataFlow, FormFactor,
+        Role, VolumeRange,
+    },
     error::{APIError, UnexpectedErr},
+    policy::{FallbackReason, SafeguardAction},
+    profile::ProfileReport,
+    routing::AppRoutes,
+    state::BackendState,
 };
 
-pub type AudioDeviceMap = BTreeMap<String, IMMAudioDevice>;
+pub type AudioDeviceMap<E> = BTreeMap<String, E>;
+
+/// Default device id per direction and role. Roles without a default device are left out.
+pub type RoleDefaults = BTreeMap<DataFlow, BTreeMap<Role, String>>;
 
 #[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct AudioDeviceInfo {
-    id: String,
-    name: String,
-    volume: f32,
-    muted: bool,
+    pub id: String,
+    /// The alias when one is set, otherwise the same as `friendly_name`.
+    pub name: String,
+    /// The name reported by the platform.
+    pub friendly_name: String,
+    pub flow: DataFlow,
+    pub form_factor: FormFactor,
+    pub volume: f32,
+    /// `None` when the backend does not report volume in dB.
+    pub volume_db: Option<f32>,
+    pub volume_range: Option<VolumeRange>,
+    /// Limit set by the user, `1.0` when there is none.
+    pub max_volume: f32,
+    pub muted: bool,
+    pub sessions: Vec<AudioSession>,
 }
 
 impl AudioDeviceInfo {
-    fn from_audio(audio: &IMMAudioDevice) -> Result<Self> {
+    pub fn from_audio<E: AudioEndpoint>(audio: &E) -> Result<Self> {
         Ok(Self {
-            id: audio.id.clone(),
-            name: audio.name.clone(),
+            id: audio.id().to_string(),
+            name: audio.name().to_string(),
+            friendly_name: audio.name().to_string(),
+            flow: audio.flow(),
+            form_factor: audio.form_factor(),
             volume: audio.get_volume()?,
+            // muted devices may report -inf, which JSON cannot carry
+            volume_db: audio.get_volume_db().ok().filter(|db| db.is_finite()),
+            volume_range: audio.get_volume_range().ok(),
+            max_volume: 1.0,
             muted: audio.get_mute_state()?,
+            sessions: audio.get_sessions()?,
         })
     }
 }
@@ -35,20 +65,51 @@ impl AudioDeviceInfo {
 #[derive(serde::Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct WindowsAudioState {
-    audio_device_list: Vec<AudioDeviceInfo>,
-    default: String,
+    /// Visible devices, in the order set by the user.
+    pub audio_device_list: Vec<AudioDeviceInfo>,
+    /// Devices hidden by the user, so they can be shown again.
+    pub hidden_device_list: Vec<AudioDeviceInfo>,
+    pub default: String,
+    /// `None` when no microphone is connected.
+    pub default_input: Option<String>,
+    pub role_defaults: RoleDefaults,
+    pub app_routes: AppRoutes,
+    pub profiles: Vec<String>,
 }
 
 impl WindowsAudioState {
-    fn new(audio_dict: &AudioDeviceMap, default: String) -> Result<Self> {
-        let audio_device_list = audio_dict
+    fn new<E: AudioEndpoint>(
+        audio_dict: &AudioDeviceMap<E>,
+        default: String,
+        default_input: Option<String>,
+        role_defaults: RoleDefaults,
+        state: &BackendState,
+    ) -> Result<Self> {
+        let preferences = &state.preferences;
+
+        let mut devices = audio_dict
             .values()
             .map(|a| AudioDeviceInfo::from_audio(a))
             .collect::<Result<Vec<_>>>()?;
+        devices.sort_by_key(|d| preferences.order(&d.id));
+
+        let (hidden_device_list, audio_device_list) = devices
+            .into_iter()
+            .map(|mut d| {
+                d.name = preferences.display_name(&d.id, &d.friendly_name);
+                d.max_volume = preferences.max_volume(&d.id);
+                d
+            })
+            .partition(|d| preferences.is_hidden(&d.id, &d.friendly_name));
 
         Ok(Self {
             audio_device_list,
+            hidden_device_list,
             default,
+            default_input,
+            role_defaults,
+            app_routes: state.app_routing.routes().clone(),
+            profiles: state.profiles.names(),
         })
     }
 }
@@ -58,25 +119,73 @@ impl WindowsAudioState {
 pub struct AudioStateChangePayload {
     windows_audio_state: WindowsAudioState,
     notification: Option<Notification>,
+    reports: Vec<Report>,
+}
+
+impl AudioStateChangePayload {
+    pub fn windows_audio_state(&self) -> &WindowsAudioState {
+        &self.windows_audio_state
+    }
 }
 
-pub async fn ipc_sender(
-    is: &Arc<Singleton>,
-    audio_dict: &Arc<Mutex<AudioDeviceMap>>,
+/// Outcome of something the user asked for, for results the platform does not notify.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum Report {
+    ProfileApplied(ProfileReport),
+    /// A higher-priority output was connected and made the default.
+    AutoSwitched {
+        id: String,
+        previous: Option<String>,
+        roles: Vec<Role>,
+    },
+    /// The default output went away and a replacement was chosen.
+    FallbackSelected {
+        removed: String,
+        /// The new default, `None` when there is no output left.
+        id: Option<String>,
+        muted: bool,
+        reason: FallbackReason,
+    },
+    /// The previous default was unplugged and the new one was muted or turned down.
+    Safeguarded {
+        id: String,
+        removed: String,
+        action: SafeguardAction,
+    },
+    /// The volume went above the device's limit and was pulled back to it.
+    #[serde(rename_all = "camelCase")]
+    VolumeLimited {
+        id: String,
+        volume: f32,
+        max_volume: f32,
+    },
+}
+
+pub async fn ipc_sender<B: AudioBackend>(
+    is: &Arc<B>,
+    audio_dict: &Arc<Mutex<AudioDeviceMap<B::Endpoint>>>,
+    state: &BackendState,
     notification: Option<Notification>,
+    reports: Vec<Report>,
     tx: &Sender<AudioStateChangePayload>,
 ) -> Result<()> {
-    let default = is.get_default_audio_id()?;
+    let default = is.get_default_audio_id(DataFlow::Render, Role::Multimedia)?;
+    let default_input = is
+        .get_default_audio_id(DataFlow::Capture, Role::Multimedia)
+        .ok();
+    let role_defaults = get_role_defaults(&**is);
     let audio_state = {
         let dict = audio_dict.lock().map_err(|_| APIError::Unexpected {
             inner: UnexpectedErr::LockError,
         })?;
-        WindowsAudioState::new(&dict, default)?
+        WindowsAudioState::new(&dict, default, default_input, role_defaults, state)?
     };
 
     let payload = AudioStateChangePayload {
         windows_audio_state: audio_state,
         notification,
+        reports,
     };
 
     tx.send(payload).await.map_err(|_| APIError::Unexpected {
@@ -85,3 +194,16 @@ pub async fn ipc_sender(
 
     Ok(())
 }
+
+pub fn get_role_defaults<B: AudioBackend>(is: &B) -> RoleDefaults {
+    let mut role_defaults = RoleDefaults::new();
+    for flow in [DataFlow::Render, DataFlow::Capture] {
+        for role in Role::ALL {
+            if let Ok(id) = is.get_default_audio_id(flow, role) {
+                role_defaults.entry(flow).or_default().insert(role, id);
+            }
+        }
+    }
+
+    role_defaults
+}
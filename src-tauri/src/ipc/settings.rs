@@ -0,0 +1,403 @@
+use anyhow::{anyhow, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tauri::{App, Emitter, State, Wry};
+use tokio::sync::watch;
+
+use crate::hotkey::{self, HotkeyBinding};
+
+use super::{
+    error::{APIError, UnexpectedErr},
+    policy::{AutoSwitchSettings, FallbackSettings, SafeguardAction, SafeguardSettings},
+    preferences::{DevicePreference, DevicePreferences},
+    store,
+};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Bumped whenever a field changes meaning, so older files can be migrated on load.
+pub const SETTINGS_VERSION: u32 = 1;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub version: u32,
+    pub hotkeys: Vec<HotkeyBinding>,
+    /// Volume change in percent per mouse wheel notch over the tray icon.
+    pub tray_scroll_step: f32,
+    /// Peak meter samples per second while the window is shown.
+    pub meter_rate: u32,
+    /// Keyed by device id.
+    pub devices: BTreeMap<String, DevicePreference>,
+    /// Regular expressions hiding every device whose name matches.
+    pub hide_rules: Vec<String>,
+    /// Output device ids, most preferred first.
+    pub priority: Vec<String>,
+    pub auto_switch: AutoSwitchSettings,
+    pub fallback: FallbackSettings,
+    pub safeguard: SafeguardSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            hotkeys: Vec::new(),
+            tray_scroll_step: 2.0,
+            meter_rate: 30,
+            devices: BTreeMap::new(),
+            hide_rules: Vec::new(),
+            priority: Vec::new(),
+            auto_switch: AutoSwitchSettings::default(),
+            fallback: FallbackSettings::default(),
+            safeguard: SafeguardSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), APIError> {
+        if self.version != SETTINGS_VERSION {
+            return Err(APIError::invalid_argument(
+                "version",
+                format!(
+                    "Unsupported settings version {} (expected {})",
+                    self.version, SETTINGS_VERSION
+                ),
+            ));
+        }
+
+        if !(self.tray_scroll_step > 0.0 && self.tray_scroll_step <= 100.0) {
+            return Err(APIError::invalid_argument(
+                "trayScrollStep",
+                format!("must be in (0, 100]: {}", self.tray_scroll_step),
+            ));
+        }
+
+        if !(1..=120).contains(&self.meter_rate) {
+            return Err(APIError::invalid_argument(
+                "meterRate",
+                format!("must be in [1, 120]: {}", self.meter_rate),
+            ));
+        }
+
+        if let SafeguardAction::Volume { volume } = self.safeguard.action {
+            if !(0.0..=1.0).contains(&volume) {
+                return Err(APIError::invalid_argument(
+                    "safeguard",
+                    format!("volume must be in [0, 1]: {}", volume),
+                ));
+            }
+        }
+
+        hotkey::parse_bindings(&self.hotkeys)
+            .map_err(|e| APIError::invalid_argument("hotkeys", e))?;
+
+        DevicePreferences::new(self).map_err(|e| APIError::from_error(e, "@DevicePreferences"))?;
+
+        Ok(())
+    }
+
+    /// Brings settings written by an older version up to date.
+    fn migrate(mut self) -> Result<Self> {
+        if self.version > SETTINGS_VERSION {
+            return Err(anyhow!(
+                "Settings version {} is newer than this app supports",
+                self.version
+            ));
+        }
+
+        // no field has changed meaning yet
+        self.version = SETTINGS_VERSION;
+
+        Ok(self)
+    }
+}
+
+/// `settings.json` in the config dir. The file may also be edited by hand while the app runs.
+pub struct SettingsStore {
+    path: PathBuf,
+    tx: watch::Sender<Settings>,
+    /// Why the file on disk could not be read, if it could not.
+    ///
+    /// Saving is refused while this is set, so a file with a mistake made by hand is not
+    /// replaced by the defaults the app fell back to.
+    load_error: Mutex<Option<String>>,
+}
+
+impl SettingsStore {
+    pub fn open(config_dir: &Path) -> Self {
+        let path = config_dir.join(SETTINGS_FILE);
+        let (settings, load_error) = match Self::read(&path) {
+            Ok(settings) => (settings, None),
+            Err(e) => {
+                log::error!("Failed to load {:?}: {:?}", path, e);
+                (Settings::default(), Some(format!("{:#}", e)))
+            }
+        };
+
+        let (tx, _) = watch::channel(settings);
+
+        Self {
+            path,
+            tx,
+            load_error: Mutex::new(load_error),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Settings> {
+        let settings = store::load::<Settings>(path)?.migrate()?;
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    pub fn get(&self) -> Settings {
+        self.tx.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Settings> {
+        self.tx.subscribe()
+    }
+
+    pub fn update(&self, settings: Settings) -> Result<(), APIError> {
+        settings.validate()?;
+
+        if let Some(e) = self.load_error()?.as_deref() {
+            return Err(APIError::SettingsUnreadable {
+                path: self.path.display().to_string(),
+                reason: e.to_string(),
+            });
+        }
+
+        store::save(&self.path, &settings).map_err(|e| APIError::SomethingWrong {
+            msg: format!("@store.save {:?}", e),
+        })?;
+        self.tx
+            .send_if_modified(|current| replace(current, settings));
+
+        Ok(())
+    }
+
+    fn load_error(&self) -> Result<MutexGuard<'_, Option<String>>, APIError> {
+        self.load_error.lock().map_err(|_| APIError::Unexpected {
+            inner: UnexpectedErr::LockError,
+        })
+    }
+
+    /// Picks up changes made to the file by hand. Invalid files are logged and otherwise ignored,
+    /// until they are fixed.
+    fn reload(&self) {
+        let result = Self::read(&self.path);
+        match self.load_error() {
+            Ok(mut load_error) => *load_error = result.as_ref().err().map(|e| format!("{:#}", e)),
+            Err(e) => log::error!("{:?}", e),
+        }
+
+        let settings = match result {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::error!("Failed to reload {:?}: {:?}", self.path, e);
+                return;
+            }
+        };
+
+        self.tx
+            .send_if_modified(|current| replace(current, settings));
+    }
+
+    /// Watches the config dir rather than the file, since saving replaces the file.
+    ///
+    /// Watching stops when the returned watcher is dropped.
+    pub fn watch(self: &Arc<Self>) -> Result<RecommendedWatcher> {
+        let store = Arc::clone(self);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    return;
+                }
+            };
+
+            let touched = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event.paths.iter().any(|p| p == &store.path);
+            if touched {
+                store.reload();
+            }
+        })?;
+
+        let dir = self
+            .path
+            .parent()
+            .ok_or_else(|| anyhow!("No parent directory: {:?}", self.path))?;
+        std::fs::create_dir_all(dir)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(watcher)
+    }
+}
+
+fn replace(current: &mut Settings, settings: Settings) -> bool {
+    if *current == settings {
+        return false;
+    }
+
+    *current = settings;
+    true
+}
+
+/// Forwards settings changes, including hand edits, to the window.
+pub fn setup(app: &mut App<Wry>, store: &SettingsStore) {
+    let app_handle = app.handle().clone();
+    let mut rx = store.subscribe();
+    tokio::spawn(async move {
+        while rx.changed().await.is_ok() {
+            let settings = rx.borrow_and_update().clone();
+            if let Err(e) = app_handle.emit("settings_change", settings) {
+                log::error!("{:?}", e);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_settings(store: State<'_, Arc<SettingsStore>>) -> Settings {
+    store.get()
+}
+
+#[tauri::command]
+pub fn update_settings(
+    store: State<'_, Arc<SettingsStore>>,
+    settings: Settings,
+) -> Result<Settings, APIError> {
+    store.update(settings)?;
+
+    Ok(store.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{hotkey::HotkeyAction, ipc::testing};
+
+    fn write(config_dir: &Path, text: &str) {
+        fs::create_dir_all(config_dir).unwrap();
+        fs::write(config_dir.join(SETTINGS_FILE), text).unwrap();
+    }
+
+    fn read(config_dir: &Path) -> String {
+        fs::read_to_string(config_dir.join(SETTINGS_FILE)).unwrap()
+    }
+
+    /// The field an invalid change to the defaults is reported for.
+    fn invalid_field(change: fn(&mut Settings)) -> String {
+        let mut settings = Settings::default();
+        change(&mut settings);
+
+        match settings.validate() {
+            Err(APIError::InvalidArgument { field, .. }) => field,
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn validates_each_field() {
+        Settings::default().validate().unwrap();
+
+        assert_eq!(
+            invalid_field(|s| s.version = SETTINGS_VERSION + 1),
+            "version"
+        );
+        assert_eq!(
+            invalid_field(|s| s.tray_scroll_step = 0.0),
+            "trayScrollStep"
+        );
+        assert_eq!(invalid_field(|s| s.meter_rate = 121), "meterRate");
+        assert_eq!(
+            invalid_field(|s| s.safeguard.action = SafeguardAction::Volume { volume: 1.5 }),
+            "safeguard"
+        );
+        assert_eq!(
+            invalid_field(|s| {
+                s.hotkeys = vec![HotkeyBinding {
+                    chord: "Ctrl+Alt+NoSuchKey".to_string(),
+                    action: HotkeyAction::ToggleMute,
+                }]
+            }),
+            "hotkeys"
+        );
+    }
+
+    #[test]
+    fn migrates_older_files() {
+        let config_dir = testing::config_dir("settings-migrate");
+        write(&config_dir, r#"{ "version": 0, "meterRate": 60 }"#);
+
+        let store = SettingsStore::open(&config_dir);
+
+        let settings = store.get();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.meter_rate, 60);
+        store.update(settings).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_overwrite_an_unreadable_file() {
+        let config_dir = testing::config_dir("settings-unreadable");
+        let broken = r#"{ "meterRate": 60, }"#;
+        write(&config_dir, broken);
+
+        let store = SettingsStore::open(&config_dir);
+        assert_eq!(store.get(), Settings::default());
+
+        let e = store.update(Settings::default()).unwrap_err();
+        assert!(matches!(e, APIError::SettingsUnreadable { .. }), "{:?}", e);
+        assert_eq!(read(&config_dir), broken);
+
+        // a newer version is kept as well, as saving would lose what it added
+        write(&config_dir, r#"{ "version": 99 }"#);
+        store.reload();
+        let e = store.update(Settings::default()).unwrap_err();
+        assert!(matches!(e, APIError::SettingsUnreadable { .. }), "{:?}", e);
+
+        // fixed by hand while the app runs
+        write(&config_dir, r#"{ "meterRate": 60 }"#);
+        store.reload();
+        assert_eq!(store.get().meter_rate, 60);
+        store.update(Settings::default()).unwrap();
+        assert_eq!(store.get(), Settings::default());
+    }
+
+    #[test]
+    fn saves_and_notifies_updates() {
+        let config_dir = testing::config_dir("settings-update");
+        let store = SettingsStore::open(&config_dir);
+        let mut rx = store.subscribe();
+
+        let settings = Settings {
+            meter_rate: 60,
+            ..Settings::default()
+        };
+        store.update(settings.clone()).unwrap();
+
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(*rx.borrow_and_update(), settings);
+        assert_eq!(SettingsStore::open(&config_dir).get(), settings);
+
+        let e = store
+            .update(Settings {
+                meter_rate: 0,
+                ..Settings::default()
+            })
+            .unwrap_err();
+        assert!(matches!(e, APIError::InvalidArgument { .. }));
+        assert_eq!(store.get(), settings);
+    }
+}
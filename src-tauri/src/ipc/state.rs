@@ -0,0 +1,41 @@
+use std::path::Path;
+
+use super::{
+    memory::VolumeMemory, policy::Policy, preferences::DevicePreferences, profile::Profiles,
+    routing::AppRouting, settings::Settings,
+};
+
+/// What the backend keeps in the config dir, next to the devices reported by the platform.
+pub struct BackendState {
+    pub app_routing: AppRouting,
+    pub profiles: Profiles,
+    pub volume_memory: VolumeMemory,
+    pub settings: Settings,
+    pub preferences: DevicePreferences,
+    pub policy: Policy,
+}
+
+impl BackendState {
+    pub fn open(config_dir: &Path, settings: Settings) -> Self {
+        let mut state = Self {
+            app_routing: AppRouting::open(config_dir),
+            profiles: Profiles::open(config_dir),
+            volume_memory: VolumeMemory::open(config_dir),
+            settings: Settings::default(),
+            preferences: DevicePreferences::default(),
+            policy: Policy::default(),
+        };
+        state.set_settings(settings);
+
+        state
+    }
+
+    pub fn set_settings(&mut self, settings: Settings) {
+        // settings are validated before they are stored, so this only fails on a broken invariant
+        self.preferences = DevicePreferences::new(&settings).unwrap_or_else(|e| {
+            log::error!("{:?}", e);
+            DevicePreferences::default()
+        });
+        self.settings = settings;
+    }
+}
@@ -0,0 +1,28 @@
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, path::Path};
+
+/// Reads a JSON file, falling back to the default value when it does not exist yet.
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+
+    let text = fs::read_to_string(path)?;
+    let value = serde_json::from_str(&text)?;
+
+    Ok(value)
+}
+
+/// Writes a JSON file through a temporary file so a crash never leaves it half written.
+pub fn save<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(value)?)?;
+    fs::rename(tmp, path)?;
+
+    Ok(())
+}
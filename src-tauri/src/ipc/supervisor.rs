@@ -0,0 +1,169 @@
+use anyhow::Result;
+use std::time::Instant;
+use tokio::{
+    sync::{mpsc::Sender, oneshot},
+    task::{JoinError, JoinHandle},
+    time::{sleep, Duration},
+};
+
+use super::error::APIError;
+
+const INITIAL_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+/// A backend which ran this long counts as healthy, so its failure starts the backoff over.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Sent to the UI as the `backend_status` event.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum BackendStatus {
+    /// The backend stopped and is rebuilt after `delay_ms`.
+    #[serde(rename_all = "camelCase")]
+    Restarting {
+        attempt: u32,
+        delay_ms: u64,
+        reason: String,
+    },
+    /// A rebuilt backend is up, with its notification callbacks registered again.
+    Recovered { attempts: u32 },
+}
+
+/// The tasks built around one instance of the audio backend.
+pub struct Generation {
+    pub relay_thread: JoinHandle<Result<()>>,
+    pub backend_thread: JoinHandle<Result<(), APIError>>,
+    /// Answered once the backend has pushed its first state, dropped if it never gets there.
+    pub ready: oneshot::Receiver<()>,
+}
+
+/// Rebuilds the backend with `spawn_generation` whenever its tasks stop, waiting longer after
+/// each failure in a row.
+///
+/// Stops for good once the backend ends without an error, which only happens as the app exits.
+pub fn spawn<F>(mut spawn_generation: F, status_tx: Sender<BackendStatus>) -> JoinHandle<()>
+where
+    F: FnMut() -> Generation + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            let Generation {
+                relay_thread,
+                backend_thread,
+                ready,
+            } = spawn_generation();
+
+            if ready.await.is_ok() && attempt > 0 {
+                log::info!("Backend recovered after {} attempts", attempt);
+                report(&status_tx, BackendStatus::Recovered { attempts: attempt }).await;
+            }
+
+            let Some(reason) = wait(relay_thread, backend_thread).await else {
+                return;
+            };
+
+            attempt = next_attempt(attempt, started.elapsed());
+
+            let delay = backoff(attempt);
+            log::error!(
+                "Backend stopped: {}. Restarting in {:?} (attempt {})",
+                reason,
+                delay,
+                attempt
+            );
+            let status = BackendStatus::Restarting {
+                attempt,
+                delay_ms: delay.as_millis() as u64,
+                reason,
+            };
+            report(&status_tx, status).await;
+
+            sleep(delay).await;
+        }
+    })
+}
+
+/// The failure in a row a backend which ran for `ran_for` counts as, `attempt` being the last.
+fn next_attempt(attempt: u32, ran_for: Duration) -> u32 {
+    if ran_for >= STABLE_AFTER {
+        1
+    } else {
+        attempt + 1
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_DELAY)
+}
+
+async fn report(status_tx: &Sender<BackendStatus>, status: BackendStatus) {
+    if let Err(e) = status_tx.send(status).await {
+        log::error!("{:?}", e);
+    }
+}
+
+/// Waits for either task to stop and stops the other, as neither is any use alone.
+///
+/// Returns why the backend has to be rebuilt, or `None` when it stopped because nothing can send
+/// it queries anymore.
+async fn wait(
+    mut relay_thread: JoinHandle<Result<()>>,
+    mut backend_thread: JoinHandle<Result<(), APIError>>,
+) -> Option<String> {
+    let relay = tokio::select! {
+        backend = &mut backend_thread => {
+            relay_thread.abort();
+            return describe(backend);
+        }
+        relay = &mut relay_thread => relay,
+    };
+
+    // a backend which stopped at the same time keeps its own result
+    backend_thread.abort();
+    match backend_thread.await {
+        Err(e) if e.is_cancelled() => Some(format!("relay_thread stopped: {:?}", relay)),
+        backend => describe(backend),
+    }
+}
+
+fn describe(backend: Result<Result<(), APIError>, JoinError>) -> Option<String> {
+    match backend {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(e) => Some(format!("backend_thread {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_starts_at_half_a_second_and_doubles() {
+        let delays: Vec<_> = (1..=5).map(backoff).collect();
+
+        assert_eq!(
+            delays,
+            [500, 1000, 2000, 4000, 8000].map(Duration::from_millis)
+        );
+    }
+
+    #[test]
+    fn backoff_stops_growing_at_30_seconds() {
+        assert_eq!(backoff(7), MAX_DELAY);
+        assert_eq!(backoff(8), MAX_DELAY);
+        assert_eq!(backoff(u32::MAX), MAX_DELAY);
+        assert_eq!(MAX_DELAY, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn backoff_starts_over_after_a_stable_minute() {
+        assert_eq!(next_attempt(0, Duration::ZERO), 1);
+        assert_eq!(next_attempt(4, Duration::from_secs(59)), 5);
+        assert_eq!(next_attempt(4, Duration::from_secs(60)), 1);
+        assert_eq!(backoff(next_attempt(6, STABLE_AFTER)), INITIAL_DELAY);
+    }
+}
@@ -0,0 +1,9 @@
+use std::path::PathBuf;
+
+/// An empty config directory of its own for the test `name`, as the backend keeps files there.
+pub fn config_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("output-switcher-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    dir
+}
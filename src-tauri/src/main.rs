@@ -1,102 +1,134 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+pub mod cli;
+pub mod hotkey;
 pub mod ipc;
+pub mod tray;
 
 use tauri::{
-    async_runtime::Sender,
-    image::Image,
-    menu::{MenuBuilder, MenuItemBuilder},
-    tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
-    Manager, State,
+    async_runtime::Sender, image::Image, tray::TrayIconBuilder, Context, Manager, State, Wry,
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use cli::CliCommand;
 use ipc::{
+    audio::{AudioBackend, DefaultBackend},
     error::{APIError, UnexpectedErr},
-    init::{prepare_backend, setup, BackendPrepareRet, IPCHandlers},
-    quit,
+    init::{prepare_backend, setup, BackendPrepareRet, IPCHandlers, QueryResult, Request},
+    quit, send_query,
+    settings::{self, get_settings, update_settings, SettingsStore},
 };
-use tauri_plugin_dialog::DialogExt;
-
+use std::{path::PathBuf, sync::Arc};
 
 #[tauri::command]
-async fn query(tx: State<'_, Sender<IPCHandlers>>, query: IPCHandlers) -> Result<(), APIError> {
+async fn query(
+    tx: State<'_, Sender<Request>>,
+    query: IPCHandlers,
+) -> Result<QueryResult, APIError> {
     log::info!("query: {:?}", query);
-    tx.send(query).await.map_err(|_| APIError::Unexpected {
+    let (request, reply) = Request::new(query);
+    tx.send(request).await.map_err(|_| APIError::Unexpected {
         inner: UnexpectedErr::MPSCClosedError,
     })?;
 
-    Ok(())
+    // dropped without an answer only when the backend loop has stopped
+    reply.await.map_err(|_| APIError::Unexpected {
+        inner: UnexpectedErr::MPSCClosedError,
+    })?
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let context = tauri::generate_context!();
+
+    let command = match cli::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            cli::attach_console();
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Some(command) = command {
+        cli::attach_console();
+        return run_command(context, command);
+    }
+
+    let config_dir = config_dir(&context)?;
+
+    let settings_store = Arc::new(SettingsStore::open(&config_dir));
+    // kept until the app exits, dropping it stops watching
+    let _settings_watcher = settings_store
+        .watch()
+        .map_err(|e| log::error!("Failed to watch settings: {:?}", e))
+        .ok();
+
     let BackendPrepareRet {
-        relay_thread,
-        backend_thread,
+        supervisor_thread,
+        settings_thread,
         ipc_tx,
         ipc_rx,
-    } = prepare_backend().await?;
+        meter_rx,
+        status_rx,
+    } = prepare_backend(DefaultBackend::new, config_dir, settings_store.subscribe()).await?;
 
     tauri::Builder::default()
+        .plugin(tauri_plugin_single_instance::init(|app, argv, _cwd| {
+            match cli::parse(argv.iter().skip(1)) {
+                Ok(Some(command)) => {
+                    if let Some(query) = command.into_query() {
+                        send_query(app, query);
+                    }
+                }
+                // started again without arguments
+                Ok(None) => {
+                    if let Some(webview_window) = app.get_webview_window("main") {
+                        let _ = webview_window.show();
+                        let _ = webview_window.set_focus();
+                    }
+                }
+                Err(e) => log::error!("{:?}", e),
+            }
+        }))
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(hotkey::on_shortcut)
+                .build(),
+        )
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_window_state::Builder::default().build())
-        .invoke_handler(tauri::generate_handler![query, quit])
+        .invoke_handler(tauri::generate_handler![
+            query,
+            quit,
+            get_settings,
+            update_settings
+        ])
         .manage(ipc_tx)
-        .setup(|app| {
-            setup(app, ipc_rx);
+        .manage(Arc::clone(&settings_store))
+        .setup(move |app| {
+            settings::setup(app, &settings_store);
+            hotkey::setup(app, &settings_store);
 
-            let quit_menu = MenuItemBuilder::with_id("quit", "終了").build(app)?;
-            let version_menu = MenuItemBuilder::with_id("version", "バージョン情報").build(app)?;
+            // devices and profiles are filled in by the first audio_state_change
+            app.manage(tray::ShownMenu::default());
+            let menu = tray::build_menu(app, &tray::MenuState::default())?;
 
-            let menu = MenuBuilder::new(app)
-                .item(&version_menu)
-                .item(&quit_menu)
-                .build()?;
-
-            let tray = TrayIconBuilder::new()
+            let tray = TrayIconBuilder::with_id(tray::TRAY_ID)
                 .menu(&menu)
-                .on_menu_event(move |app, event| match event.id().as_ref() {
-                    "quit" => {
-                        let app = app.clone();
-                        quit(app);
-                    }
-                    "version" => {
-                        let message = format!(
-                            "{} v{}",
-                            app.package_info().name,
-                            app.package_info().version
-                        );
-
-                        app.dialog()
-                            .message(message)
-                            .title("バージョン情報")
-                            .blocking_show();
-                    }
-                    _ => (),
-                })
-                .on_tray_icon_event(move |tray, event| {
-                    if let TrayIconEvent::Click {
-                        button: MouseButton::Left,
-                        button_state: MouseButtonState::Up,
-                        ..
-                    } = event
-                    {
-                        let app = tray.app_handle();
-                        if let Some(webview_window) = app.get_webview_window("main") {
-                            let _ = webview_window.show();
-                            let _ = webview_window.set_focus();
-                        }
-                    }
-                })
+                .on_menu_event(tray::on_menu_event)
+                .on_tray_icon_event(tray::on_tray_icon_event)
                 .build(app)?;
 
             let icon = include_bytes!("../icons/icon.ico").to_vec();
             let image = Image::from_bytes(&icon).expect("Failed to load icon image");
 
             tray.set_icon(Some(image)).expect("Failed to set tray icon");
+            tray::setup_scroll(app.handle());
+
+            // the tray has to exist before the first state arrives
+            setup(app, ipc_rx, meter_rx, status_rx);
 
             #[cfg(debug_assertions)]
             {
@@ -106,18 +138,60 @@ async fn main() -> Result<()> {
 
             Ok(())
         })
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 
-    let e = relay_thread.await;
+    let e = settings_thread.await;
     if let Err(e) = e {
-        log::error!("relay_thread end with Error: {:?}", e);
+        log::error!("settings_thread end with Error: {:?}", e);
     }
 
-    let e = backend_thread.await;
+    let e = supervisor_thread.await;
     if let Err(e) = e {
-        log::error!("backend_thread end with Error: {:?}", e);
+        log::error!("supervisor_thread end with Error: {:?}", e);
     }
 
     Ok(())
 }
+
+/// Same location as PathResolver::app_config_dir, which is only available once the app runs.
+fn config_dir(context: &Context<Wry>) -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| anyhow!("No config directory"))?
+        .join(&context.config().identifier))
+}
+
+/// Forwards `command` to the running instance, or runs it headless when there is none.
+fn run_command(mut context: Context<Wry>, command: CliCommand) -> Result<()> {
+    // volume limits still apply without a running instance
+    let settings = SettingsStore::open(&config_dir(&context)?).get();
+
+    // printed output would end up in the running instance, so listing never forwards
+    if let CliCommand::List { .. } = command {
+        return cli::run::<DefaultBackend>(command, &settings);
+    }
+
+    // only the single-instance check is needed, so neither a window nor a tray is created
+    context.config_mut().app.windows.clear();
+    context.config_mut().app.tray_icon = None;
+
+    tauri::Builder::default()
+        // hands the arguments to the running instance and exits this process if there is one
+        .plugin(tauri_plugin_single_instance::init(|_, _, _| {}))
+        .setup(move |app| {
+            let code = match cli::run::<DefaultBackend>(command, &settings) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            };
+            app.handle().exit(code);
+
+            Ok(())
+        })
+        .run(context)
+        .expect("error while running tauri application");
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use tauri::image::Image;
+
+use crate::ipc::audio::FormFactor;
+
+const SIZE: u32 = 32;
+
+/// Samples per pixel along each axis, for anti-aliased edges.
+const SAMPLES: u32 = 4;
+
+/// tan(50°), the half angle of the speaker's sound waves.
+const WAVE_SLOPE: f32 = 1.19;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeTier {
+    Muted,
+    Low,
+    Mid,
+    High,
+}
+
+impl VolumeTier {
+    pub fn new(volume: f32, muted: bool) -> Self {
+        if muted || volume <= 0.0 {
+            VolumeTier::Muted
+        } else if volume < 1.0 / 3.0 {
+            VolumeTier::Low
+        } else if volume < 2.0 / 3.0 {
+            VolumeTier::Mid
+        } else {
+            VolumeTier::High
+        }
+    }
+
+    fn level(self) -> usize {
+        match self {
+            VolumeTier::Muted => 0,
+            VolumeTier::Low => 1,
+            VolumeTier::Mid => 2,
+            VolumeTier::High => 3,
+        }
+    }
+}
+
+fn in_rect(x: f32, y: f32, (x0, y0): (f32, f32), (x1, y1): (f32, f32)) -> bool {
+    x0 <= x && x <= x1 && y0 <= y && y <= y1
+}
+
+/// `points` go around a convex polygon in either direction.
+fn in_polygon(x: f32, y: f32, points: &[(f32, f32)]) -> bool {
+    let crosses = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(&(ax, ay), &(bx, by))| (bx - ax) * (y - ay) - (by - ay) * (x - ax));
+
+    let mut sign = 0.0;
+    for cross in crosses {
+        if cross * sign < 0.0 {
+            return false;
+        }
+        if cross != 0.0 {
+            sign = cross;
+        }
+    }
+
+    true
+}
+
+fn near_segment(x: f32, y: f32, (ax, ay): (f32, f32), (bx, by): (f32, f32), width: f32) -> bool {
+    let (dx, dy) = (bx - ax, by - ay);
+    let t = (((x - ax) * dx + (y - ay) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+    let (px, py) = (ax + t * dx, ay + t * dy);
+
+    (x - px).hypot(y - py) <= width / 2.0
+}
+
+fn near_circle(x: f32, y: f32, (cx, cy): (f32, f32), radius: f32, width: f32) -> bool {
+    ((x - cx).hypot(y - cy) - radius).abs() <= width / 2.0
+}
+
+fn speaker(x: f32, y: f32, tier: VolumeTier) -> bool {
+    let body = in_rect(x, y, (3.0, 12.0), (9.0, 20.0))
+        || in_polygon(x, y, &[(9.0, 12.0), (16.0, 6.0), (16.0, 26.0), (9.0, 20.0)]);
+    if body {
+        return true;
+    }
+
+    if tier == VolumeTier::Muted {
+        return near_segment(x, y, (20.0, 12.0), (28.0, 20.0), 2.6)
+            || near_segment(x, y, (20.0, 20.0), (28.0, 12.0), 2.6);
+    }
+
+    let in_cone = x > 16.0 && (y - 16.0).abs() <= (x - 16.0) * WAVE_SLOPE;
+    in_cone
+        && [5.0, 9.5, 14.0]
+            .iter()
+            .take(tier.level())
+            .any(|r| near_circle(x, y, (16.0, 16.0), *r, 2.4))
+}
+
+fn headphones(x: f32, y: f32, tier: VolumeTier) -> bool {
+    let band = y <= 18.0 && near_circle(x, y, (16.0, 18.0), 12.0, 2.6);
+    let cups = in_rect(x, y, (3.0, 17.0), (9.0, 27.0)) || in_rect(x, y, (23.0, 17.0), (29.0, 27.0));
+    if band || cups {
+        return true;
+    }
+
+    if tier == VolumeTier::Muted {
+        return near_segment(x, y, (12.5, 18.5), (19.5, 25.5), 2.4)
+            || near_segment(x, y, (12.5, 25.5), (19.5, 18.5), 2.4);
+    }
+
+    // bars between the cups, one per tier
+    (0..tier.level()).any(|i| {
+        let left = 11.5 + i as f32 * 3.5;
+        let height = 3.0 + i as f32 * 2.5;
+        in_rect(x, y, (left, 27.0 - height), (left + 2.2, 27.0))
+    })
+}
+
+/// Draws the tray icon for `tier`, as headphones or as a speaker depending on `form_factor`.
+///
+/// The glyph is white with a dark outline so it stays visible on light and dark taskbars.
+pub fn render(tier: VolumeTier, form_factor: FormFactor) -> Image<'static> {
+    let glyph: fn(f32, f32, VolumeTier) -> bool = match form_factor {
+        FormFactor::Headphones | FormFactor::Headset => headphones,
+        _ => speaker,
+    };
+
+    let coverage = (0..SIZE * SIZE)
+        .map(|i| {
+            let (px, py) = ((i % SIZE) as f32, (i / SIZE) as f32);
+            let hits = (0..SAMPLES * SAMPLES)
+                .filter(|s| {
+                    let x = px + ((s % SAMPLES) as f32 + 0.5) / SAMPLES as f32;
+                    let y = py + ((s / SAMPLES) as f32 + 0.5) / SAMPLES as f32;
+                    glyph(x, y, tier)
+                })
+                .count();
+            hits as f32 / (SAMPLES * SAMPLES) as f32
+        })
+        .collect::<Vec<_>>();
+
+    let mut rgba = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+    for i in 0..SIZE * SIZE {
+        let (px, py) = ((i % SIZE) as i32, (i / SIZE) as i32);
+        let fill = coverage[i as usize];
+
+        let mut outline: f32 = 0.0;
+        for (dx, dy) in (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (dx, dy))) {
+            let (nx, ny) = (px + dx, py + dy);
+            if (0..SIZE as i32).contains(&nx) && (0..SIZE as i32).contains(&ny) {
+                outline = outline.max(coverage[(ny * SIZE as i32 + nx) as usize]);
+            }
+        }
+
+        // white glyph over a translucent black outline
+        let alpha = fill + outline * 0.6 * (1.0 - fill);
+        let value = if alpha > 0.0 { fill / alpha } else { 0.0 };
+        let value = (value * 255.0).round() as u8;
+        rgba.extend_from_slice(&[value, value, value, (alpha * 255.0).round() as u8]);
+    }
+
+    Image::new_owned(rgba, SIZE, SIZE)
+}
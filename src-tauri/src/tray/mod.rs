@@ -0,0 +1,314 @@
+mod icon;
+#[cfg(windows)]
+mod scroll;
+
+use icon::VolumeTier;
+use std::sync::{Arc, Mutex};
+use tauri::{
+    menu::{CheckMenuItemBuilder, Menu, MenuBuilder, MenuEvent, MenuItemBuilder, SubmenuBuilder},
+    tray::{MouseButton, MouseButtonState, TrayIcon, TrayIconEvent},
+    AppHandle, Manager, Runtime,
+};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::ipc::{
+    audio::{DataFlow, FormFactor},
+    init::IPCHandlers,
+    quit, send_query,
+    sender::WindowsAudioState,
+    settings::SettingsStore,
+};
+
+pub const TRAY_ID: &str = "main";
+
+const DEVICE_PREFIX: &str = "device:";
+const MUTE_PREFIX: &str = "mute:";
+const PROFILE_PREFIX: &str = "profile:";
+
+/// An output device as far as the tray menu is concerned.
+#[derive(Debug, Clone, PartialEq)]
+struct MenuDevice {
+    id: String,
+    name: String,
+    muted: bool,
+}
+
+/// The part of `WindowsAudioState` the tray menu shows, so it is rebuilt only when this changes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MenuState {
+    outputs: Vec<MenuDevice>,
+    default: String,
+    profiles: Vec<String>,
+}
+
+impl MenuState {
+    pub fn new(state: &WindowsAudioState) -> Self {
+        let outputs = state
+            .audio_device_list
+            .iter()
+            .filter(|d| d.flow == DataFlow::Render)
+            .map(|d| MenuDevice {
+                id: d.id.clone(),
+                name: d.name.clone(),
+                muted: d.muted,
+            })
+            .collect();
+
+        Self {
+            outputs,
+            default: state.default.clone(),
+            profiles: state.profiles.clone(),
+        }
+    }
+}
+
+/// The menu state the tray menu was last built from.
+#[derive(Default)]
+pub struct ShownMenu(Mutex<MenuState>);
+
+/// What the tray icon and its tooltip show about the default output.
+#[derive(Debug, Clone, PartialEq)]
+struct IconState {
+    tooltip: String,
+    tier: VolumeTier,
+    form_factor: FormFactor,
+}
+
+impl IconState {
+    fn new(app: &AppHandle, state: &WindowsAudioState) -> Self {
+        // the default output may be hidden from the lists, but it still plays the audio
+        let default = state
+            .audio_device_list
+            .iter()
+            .chain(state.hidden_device_list.iter())
+            .find(|d| d.id == state.default);
+
+        let Some(default) = default else {
+            return Self {
+                tooltip: app.package_info().name.clone(),
+                tier: VolumeTier::Muted,
+                form_factor: FormFactor::Unknown,
+            };
+        };
+
+        let level = if default.muted {
+            "ミュート".to_string()
+        } else {
+            format!("{:.0}%", default.volume * 100.0)
+        };
+
+        Self {
+            tooltip: format!("{}\n{}", default.name, level),
+            tier: VolumeTier::new(default.volume, default.muted),
+            form_factor: default.form_factor,
+        }
+    }
+}
+
+/// What the tray currently shows, so it is only touched when something visible changed.
+#[derive(Default)]
+pub struct TrayView {
+    menu: Option<MenuState>,
+    icon: Option<IconState>,
+}
+
+impl TrayView {
+    pub fn update(&mut self, app: &AppHandle, state: &WindowsAudioState) {
+        let Some(tray) = app.tray_by_id(TRAY_ID) else {
+            return;
+        };
+
+        // volume changes arrive often, and rebuilding would close a menu the user has open
+        let menu = MenuState::new(state);
+        if self.menu.as_ref() != Some(&menu) {
+            let e = build_menu(app, &menu).and_then(|m| tray.set_menu(Some(m)));
+            if let Err(e) = e {
+                log::error!("{:?}", e);
+            }
+            match app.state::<ShownMenu>().0.lock() {
+                Ok(mut shown) => *shown = menu.clone(),
+                Err(e) => log::error!("{:?}", e),
+            }
+            self.menu = Some(menu);
+        }
+
+        let icon = IconState::new(app, state);
+        let previous = self.icon.replace(icon.clone());
+        if previous.as_ref().map(|p| &p.tooltip) != Some(&icon.tooltip) {
+            if let Err(e) = tray.set_tooltip(Some(&icon.tooltip)) {
+                log::error!("{:?}", e);
+            }
+        }
+        if previous.map(|p| (p.tier, p.form_factor)) != Some((icon.tier, icon.form_factor)) {
+            let image = icon::render(icon.tier, icon.form_factor);
+            if let Err(e) = tray.set_icon(Some(image)) {
+                log::error!("{:?}", e);
+            }
+        }
+    }
+}
+
+pub fn build_menu<R: Runtime, M: Manager<R>>(app: &M, state: &MenuState) -> tauri::Result<Menu<R>> {
+    let mut menu = MenuBuilder::new(app);
+
+    let mut mute_menu = SubmenuBuilder::new(app, "ミュート");
+    for device in state.outputs.iter() {
+        let item =
+            CheckMenuItemBuilder::with_id(format!("{}{}", DEVICE_PREFIX, device.id), &device.name)
+                .checked(device.id == state.default)
+                .build(app)?;
+        menu = menu.item(&item);
+
+        let item =
+            CheckMenuItemBuilder::with_id(format!("{}{}", MUTE_PREFIX, device.id), &device.name)
+                .checked(device.muted)
+                .build(app)?;
+        mute_menu = mute_menu.item(&item);
+    }
+    let mute_menu = mute_menu.enabled(!state.outputs.is_empty()).build()?;
+
+    let mut profile_menu = SubmenuBuilder::new(app, "プロファイル");
+    for name in state.profiles.iter() {
+        let item =
+            MenuItemBuilder::with_id(format!("{}{}", PROFILE_PREFIX, name), name).build(app)?;
+        profile_menu = profile_menu.item(&item);
+    }
+    let profile_menu = profile_menu.enabled(!state.profiles.is_empty()).build()?;
+
+    let quit_menu = MenuItemBuilder::with_id("quit", "終了").build(app)?;
+    let version_menu = MenuItemBuilder::with_id("version", "バージョン情報").build(app)?;
+
+    if !state.outputs.is_empty() {
+        menu = menu.separator();
+    }
+
+    menu.item(&mute_menu)
+        .item(&profile_menu)
+        .separator()
+        .item(&version_menu)
+        .item(&quit_menu)
+        .build()
+}
+
+pub fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+    match event.id().as_ref() {
+        "quit" => {
+            let app = app.clone();
+            quit(app);
+        }
+        "version" => {
+            let message = format!(
+                "{} v{}",
+                app.package_info().name,
+                app.package_info().version
+            );
+
+            app.dialog()
+                .message(message)
+                .title("バージョン情報")
+                .blocking_show();
+        }
+        id => {
+            let query = if let Some(id) = id.strip_prefix(DEVICE_PREFIX) {
+                IPCHandlers::DefaultAudioChange {
+                    id: id.to_string(),
+                    roles: None,
+                }
+            } else if let Some(id) = id.strip_prefix(MUTE_PREFIX) {
+                IPCHandlers::MuteChange {
+                    device: Some(id.to_string()),
+                    muted: None,
+                }
+            } else if let Some(name) = id.strip_prefix(PROFILE_PREFIX) {
+                IPCHandlers::ProfileApply {
+                    name: name.to_string(),
+                }
+            } else {
+                return;
+            };
+
+            send_query(app, query);
+
+            // a click toggles the check mark by itself, even when it changes nothing or fails,
+            // and the state only rebuilds the menu when it changed
+            if !id.starts_with(PROFILE_PREFIX) {
+                reset_checks(app);
+            }
+        }
+    }
+}
+
+/// Rebuilds the menu from the last state, undoing check marks toggled by clicks.
+fn reset_checks(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+
+    let e = match app.state::<ShownMenu>().0.lock() {
+        Ok(shown) => build_menu(app, &shown).and_then(|m| tray.set_menu(Some(m))),
+        Err(e) => {
+            log::error!("{:?}", e);
+            return;
+        }
+    };
+    if let Err(e) = e {
+        log::error!("{:?}", e);
+    }
+}
+
+pub fn on_tray_icon_event(tray: &TrayIcon, event: TrayIconEvent) {
+    let app = tray.app_handle();
+
+    match event {
+        TrayIconEvent::Click {
+            button: MouseButton::Left,
+            button_state: MouseButtonState::Up,
+            ..
+        } => {
+            if let Some(webview_window) = app.get_webview_window("main") {
+                let _ = webview_window.show();
+                let _ = webview_window.set_focus();
+            }
+        }
+        TrayIconEvent::Click {
+            button: MouseButton::Middle,
+            button_state: MouseButtonState::Up,
+            ..
+        } => send_query(
+            app,
+            IPCHandlers::MuteChange {
+                device: None,
+                muted: None,
+            },
+        ),
+        #[cfg(windows)]
+        TrayIconEvent::Enter { .. } => scroll::set_hovered(true),
+        #[cfg(windows)]
+        TrayIconEvent::Leave { .. } => scroll::set_hovered(false),
+        _ => (),
+    }
+}
+
+/// Starts listening for the mouse wheel over the tray icon.
+///
+/// Tray icons get no wheel events of their own, so this is only supported on Windows for now.
+pub fn setup_scroll(app: &AppHandle) {
+    #[cfg(windows)]
+    scroll::start(app.clone());
+
+    #[cfg(not(windows))]
+    let _ = app;
+}
+
+/// Steps the default output's volume by the configured step per wheel notch.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn on_scroll(app: &AppHandle, notches: f32) {
+    let step = app.state::<Arc<SettingsStore>>().get().tray_scroll_step;
+    send_query(
+        app,
+        IPCHandlers::VolumeStep {
+            device: None,
+            step: notches * step / 100.0,
+        },
+    );
+}
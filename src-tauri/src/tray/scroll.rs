@@ -0,0 +1,57 @@
+// https://learn.microsoft.com/ja-jp/windows/win32/winmsg/lowlevelmouseproc
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    OnceLock,
+};
+use tauri::AppHandle;
+use windows::Win32::{
+    Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
+    System::LibraryLoader::GetModuleHandleW,
+    UI::WindowsAndMessaging::{
+        CallNextHookEx, GetMessageW, SetWindowsHookExW, HHOOK, MSG, MSLLHOOKSTRUCT, WHEEL_DELTA,
+        WH_MOUSE_LL, WM_MOUSEWHEEL,
+    },
+};
+
+/// Set from the tray's Enter and Leave events, as the hook sees every wheel event on the desktop.
+static HOVERED: AtomicBool = AtomicBool::new(false);
+
+static APP: OnceLock<AppHandle> = OnceLock::new();
+
+pub fn set_hovered(hovered: bool) {
+    HOVERED.store(hovered, Ordering::Relaxed);
+}
+
+/// Installs a low-level mouse hook on a thread of its own, which needs a message loop to run.
+pub fn start(app: AppHandle) {
+    if APP.set(app).is_err() {
+        return;
+    }
+
+    std::thread::spawn(|| unsafe {
+        let hook = GetModuleHandleW(None)
+            .and_then(|module| SetWindowsHookExW(WH_MOUSE_LL, Some(hook), HINSTANCE(module.0), 0));
+        if let Err(e) = hook {
+            log::error!("@SetWindowsHookExW {:?}", e);
+            return;
+        }
+
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, HWND::default(), 0, 0).as_bool() {}
+    });
+}
+
+unsafe extern "system" fn hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 && wparam.0 as u32 == WM_MOUSEWHEEL && HOVERED.load(Ordering::Relaxed) {
+        let info = &*(lparam.0 as *const MSLLHOOKSTRUCT);
+        // the high word is the signed wheel delta
+        let delta = (info.mouseData >> 16) as u16 as i16;
+
+        if let Some(app) = APP.get() {
+            super::on_scroll(app, delta as f32 / WHEEL_DELTA as f32);
+        }
+    }
+
+    CallNextHookEx(HHOOK::default(), code, wparam, lparam)
+}
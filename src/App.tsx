@@ -1,10 +1,11 @@
-import { Card, CardContent, CircularProgress, CssBaseline, Stack } from "@mui/material";
+import { Card, CardContent, CircularProgress, CssBaseline, Snackbar, Stack } from "@mui/material";
 import { LogicalSize } from "@tauri-apps/api/dpi";
 import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
-import { useCallback, useEffect, useMemo, useRef } from "react";
+import { useCallback, useEffect, useMemo, useRef, useState } from "react";
 import AppContext from "./AppContext";
 import Meter from "./Meter";
 import ThemeProvider from "./ThemeProvider";
+import useBackendStatus from "./useBackendStatus";
 import useWindowsAudioState from "./useWindowsAudioState";
 
 function App() {
@@ -63,6 +64,12 @@ function App() {
   }, [])
 
   const audioState = useWindowsAudioState();
+  const backendStatus = useBackendStatus();
+  const [recoveredShown, setRecoveredShown] = useState(false);
+
+  useEffect(() => {
+    setRecoveredShown(backendStatus?.type === "Recovered");
+  }, [backendStatus]);
 
   const defaultDevice = useMemo(() => {
     if (!audioState) {
@@ -98,7 +105,7 @@ function App() {
               <Meter
                 device={defaultDevice}
                 defaultVolume={getVolume(defaultDevice.id)}
-                deviceList={audioState?.audioDeviceList}
+                deviceList={audioState?.audioDeviceList.filter((device) => device.flow === defaultDevice.flow)}
               />
             )}
 
@@ -110,6 +117,17 @@ function App() {
 
           </CardContent>
         </Card>
+
+        <Snackbar
+          open={backendStatus?.type === "Restarting" || recoveredShown}
+          autoHideDuration={backendStatus?.type === "Recovered" ? 3000 : null}
+          onClose={() => setRecoveredShown(false)}
+          message={
+            backendStatus?.type === "Restarting"
+              ? `Audio backend stopped, restarting (attempt ${backendStatus.attempt})`
+              : "Audio backend recovered"
+          }
+        />
       </AppContext.Provider>
     </ThemeProvider>
   );
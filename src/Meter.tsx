@@ -1,13 +1,14 @@
 import VolumeMuteIcon from '@mui/icons-material/VolumeMute';
 import VolumeOffIcon from '@mui/icons-material/VolumeOff';
 import VolumeUpIcon from '@mui/icons-material/VolumeUp';
-import { Grid, IconButton, Slider, Stack, Typography } from "@mui/material";
+import { Grid, IconButton, LinearProgress, Slider, Snackbar, Stack, Typography } from "@mui/material";
 import { invoke } from "@tauri-apps/api/core";
 import { type UnlistenFn, listen } from "@tauri-apps/api/event";
 import { useCallback, useContext, useEffect, useRef, useState } from "react";
 import AppContext from "./AppContext";
-import { invokeQuery } from "./ipc";
-import type { MeterProps } from "./types";
+import { describeError, invokeQuery } from "./ipc";
+import type { APIError, MeterProps } from "./types";
+import usePeakMeter from './usePeakMeter';
 import useRegisterContextMenu from './useRegisterContextMenu';
 
 const volumeStep = 0.01;
@@ -73,6 +74,10 @@ export default function Meter(props: MeterProps) {
   useEffect(() => setVolume(defaultVolume || 0), [defaultVolume]);
   useEffect(() => setMuted(device.muted), [device.muted]);
 
+  const [error, setError] = useState<APIError | null>(null);
+
+  const levels = usePeakMeter(device.id);
+
   const handlerIdRef = useRef<number | null>(null);
   const invokeChangeVolume = useCallback(async (volume: number) => {
     if (!device) {
@@ -84,24 +89,35 @@ export default function Meter(props: MeterProps) {
     }
 
     handlerIdRef.current = window.setTimeout(async () => {
-      await invokeQuery({
-        kind: "VolumeChange",
-        id: device.id,
-        volume,
-      });
+      try {
+        const result = await invokeQuery({
+          kind: "VolumeChange",
+          id: device.id,
+          volume,
+        });
+        // the backend may have clamped it to the device's limit
+        if (result.type === "Volume") {
+          setVolume(result.volume);
+        }
+      } catch (e) {
+        console.error(e);
+        setError(e as APIError);
+        setVolume(device.volume);
+      }
     }, 10);
 
-  }, [device.id]);
+  }, [device.id, device.volume]);
 
   const handleChangeVolume = useCallback((event: Event, volume: number | number[]) => {
 
     event.stopPropagation();
     event.preventDefault();
 
-    setVolume(volume as number);
-    invokeChangeVolume(volume as number);
+    const nextVolume = Math.min(device.maxVolume, volume as number);
+    setVolume(nextVolume);
+    invokeChangeVolume(nextVolume);
 
-  }, [invokeChangeVolume])
+  }, [invokeChangeVolume, device.maxVolume])
 
   const handleWheel = useCallback((event: WheelEvent) => {
 
@@ -121,7 +137,7 @@ export default function Meter(props: MeterProps) {
       const delta = event.deltaY || event.deltaX;
 
       const direction = volume + (delta > 0 ? -volumeStep : volumeStep);
-      const nextVolume = Math.min(1, Math.max(0, direction));
+      const nextVolume = Math.min(device.maxVolume, Math.max(0, direction));
 
       invokeChangeVolume(nextVolume);
 
@@ -129,7 +145,7 @@ export default function Meter(props: MeterProps) {
     })
 
 
-  }, [invokeChangeVolume, muted]);
+  }, [invokeChangeVolume, muted, device.maxVolume]);
 
   const scrollAreaRef = useRef<HTMLDivElement | null>(null);
   useEffect(() => {
@@ -153,11 +169,17 @@ export default function Meter(props: MeterProps) {
 
     setMuted(!muted);
 
-    await invokeQuery({
-      kind: "MuteStateChange",
-      id: device.id,
-      muted: !muted,
-    });
+    try {
+      await invokeQuery({
+        kind: "MuteStateChange",
+        id: device.id,
+        muted: !muted,
+      });
+    } catch (e) {
+      console.error(e);
+      setError(e as APIError);
+      setMuted(muted);
+    }
 
   }, [device, muted]);
 
@@ -217,15 +239,33 @@ export default function Meter(props: MeterProps) {
           min={0}
           max={1}
           step={volumeStep}
+          marks={device.maxVolume < 1 ? [{ value: device.maxVolume }] : false}
           disabled={muted}
           size="small"
           ref={sliderRef}
         />
-        <Typography variant="body1" textAlign="center" width="2em">
+        <Typography
+          variant="body1"
+          textAlign="center"
+          width="2em"
+          title={device.volumeDb !== null ? `${device.volumeDb.toFixed(1)} dB` : undefined}
+        >
           {displayVolume(volume)}
         </Typography>
       </Stack>
 
+      <LinearProgress
+        variant="determinate"
+        value={(levels?.peak ?? 0) * 100}
+      />
+
+      <Snackbar
+        open={error !== null}
+        autoHideDuration={4000}
+        onClose={() => setError(null)}
+        message={error ? describeError(error) : ""}
+      />
+
     </Grid>
   )
 }
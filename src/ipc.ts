@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import { APIError, QueryResult, Role, Settings } from "./types";
 
 export type AudioDict = {
   kind: "AudioDict";
@@ -7,6 +8,8 @@ export type AudioDict = {
 export type DefaultAudioChange = {
   kind: "DefaultAudioChange";
   id: string;
+  /** every role when omitted */
+  roles?: Role[];
 };
 
 export type VolumeChange = {
@@ -21,15 +24,109 @@ export type MuteStateChange = {
   muted: boolean;
 };
 
+export type VolumeDbChange = {
+  kind: "VolumeDbChange";
+  id: string;
+  /** clamped into the device's volume range */
+  db: number;
+};
+
+export type SessionVolumeChange = {
+  kind: "SessionVolumeChange";
+  id: string;
+  pid: number;
+  volume: number;
+};
+
+export type SessionMuteChange = {
+  kind: "SessionMuteChange";
+  id: string;
+  pid: number;
+  muted: boolean;
+};
+
+export type AppDeviceChange = {
+  kind: "AppDeviceChange";
+  app: string;
+  id: string | null;
+};
+
+export type ProfileSave = {
+  kind: "ProfileSave";
+  name: string;
+};
+
+export type ProfileApply = {
+  kind: "ProfileApply";
+  name: string;
+};
+
+export type ProfileRemove = {
+  kind: "ProfileRemove";
+  name: string;
+};
+
+export type SwitchDevice = {
+  kind: "SwitchDevice";
+  /** device id or name */
+  device: string;
+};
+
+export type SwitchNext = {
+  kind: "SwitchNext";
+};
+
+export type VolumeStep = {
+  kind: "VolumeStep";
+  /** the default output when null */
+  device: string | null;
+  step: number;
+};
+
+export type VolumeHardwareStep = {
+  kind: "VolumeHardwareStep";
+  /** the default output when null */
+  device: string | null;
+  /** one step of the device's own volume control */
+  up: boolean;
+};
+
+export type MuteChange = {
+  kind: "MuteChange";
+  /** the default output when null */
+  device: string | null;
+  /** toggles when null */
+  muted: boolean | null;
+};
+
 export type Channels = {
   kind: "Channels";
 };
 
 
-export type Query = AudioDict | DefaultAudioChange | VolumeChange | MuteStateChange | Channels;
+export type Metering = {
+  kind: "Metering";
+  /** sent as the window is shown and hidden */
+  enabled: boolean;
+};
+
+export type Query = AudioDict | DefaultAudioChange | VolumeChange | VolumeDbChange | MuteStateChange | SessionVolumeChange | SessionMuteChange | AppDeviceChange | ProfileSave | ProfileApply | ProfileRemove | SwitchDevice | SwitchNext | VolumeStep | VolumeHardwareStep | MuteChange | Metering | Channels;
 
 export type QueryKind = Query["kind"];
 
-export async function invokeQuery(query: Query): Promise<void> {
-  await invoke("query", { query });
+/** Resolves once the backend has handled the query, and rejects with an `APIError`. */
+export async function invokeQuery(query: Query): Promise<QueryResult> {
+  return await invoke("query", { query });
+}
+
+export function describeError(error: APIError): string {
+  return error.message;
+}
+
+export async function getSettings(): Promise<Settings> {
+  return await invoke("get_settings");
+}
+
+export async function updateSettings(settings: Settings): Promise<Settings> {
+  return await invoke("update_settings", { settings });
 }
@@ -1,18 +1,51 @@
+export type DataFlow = "Render" | "Capture";
+export type Role = "Console" | "Multimedia" | "Communications";
+
+export type FormFactor = "Speakers" | "Headphones" | "Headset" | "Microphone" | "Unknown";
+
+export interface VolumeRange {
+  minDb: number;
+  maxDb: number;
+  incrementDb: number;
+}
+
 export interface AudioDeviceInfo {
   id: string;
+  /** the alias when one is set */
   name: string;
+  /** the name reported by the OS */
+  friendlyName: string;
+  flow: DataFlow;
+  formFactor: FormFactor;
   volume: number;
+  /** null when the backend does not report volume in dB */
+  volumeDb: number | null;
+  volumeRange: VolumeRange | null;
+  /** limit set by the user, 1 when there is none */
+  maxVolume: number;
   muted: boolean;
   sessions: AudioSessionInfo[];
 }
 
 export interface WindowsAudioState {
   default: string;
+  defaultInput: string | null;
+  /** default device id per direction and role */
+  roleDefaults: Partial<Record<DataFlow, Partial<Record<Role, string>>>>;
+  /** visible devices, in the order set by the user */
   audioDeviceList: AudioDeviceInfo[];
+  hiddenDeviceList: AudioDeviceInfo[];
+  /** executable name to device id */
+  appRoutes: Record<string, string>;
+  /** names of the saved profiles */
+  profiles: string[];
 }
 
-export interface AudioSessionInfo extends AudioDeviceInfo {
-  icon: string | null;
+export interface AudioSessionInfo {
+  pid: number;
+  name: string;
+  volume: number;
+  muted: boolean;
 }
 
 const eventNames = [
@@ -22,6 +55,10 @@ const eventNames = [
   "DeviceStateChanged",
   "PropertyValueChanged",
   "VolumeChanged",
+  "SessionCreated",
+  "SessionVolumeChanged",
+  "SessionStateChanged",
+  "SessionDisconnected",
 ] as const;
 
 export type EventName = typeof eventNames[number];
@@ -32,7 +69,10 @@ interface EventPayloadBase {
   id: string;
 }
 
-export interface DefaultDeviceChanged extends EventPayloadBase { }
+export interface DefaultDeviceChanged extends EventPayloadBase {
+  flow: DataFlow;
+  role: Role;
+}
 export interface DeviceAdded extends EventPayloadBase { }
 export interface DeviceRemoved extends EventPayloadBase { }
 export interface DeviceStateChanged extends EventPayloadBase {
@@ -46,17 +86,187 @@ export interface VolumeChanged extends EventPayloadBase {
   muted: boolean;
 }
 
-export type Notify = | DefaultDeviceChanged | DeviceAdded | DeviceRemoved | DeviceStateChanged | PropertyValueChanged | VolumeChanged;
+export interface SessionCreated extends EventPayloadBase {
+  pid: number;
+}
+export interface SessionVolumeChanged extends EventPayloadBase {
+  pid: number;
+  volume: number;
+  muted: boolean;
+}
+export interface SessionStateChanged extends EventPayloadBase {
+  pid: number;
+  state: number;
+}
+export interface SessionDisconnected extends EventPayloadBase {
+  pid: number;
+  reason: number;
+}
+
+export type Notify = | DefaultDeviceChanged | DeviceAdded | DeviceRemoved | DeviceStateChanged | PropertyValueChanged | VolumeChanged | SessionCreated | SessionVolumeChanged | SessionStateChanged | SessionDisconnected;
+
 
 
+export interface ProfileApplied {
+  type: "ProfileApplied";
+  name: string;
+  /** ids of devices the profile refers to which are not connected */
+  missing: string[];
+  errors: string[];
+}
+
+export interface AutoSwitched {
+  type: "AutoSwitched";
+  id: string;
+  previous: string | null;
+  roles: Role[];
+}
+
+export interface FallbackSelected {
+  type: "FallbackSelected";
+  /** the default output which went away */
+  removed: string;
+  id: string | null;
+  muted: boolean;
+  reason: "Ranked" | "AlreadyDefault" | "NoRankedDevice";
+}
+
+export type SafeguardAction =
+  | { type: "Mute" }
+  /** 0.0 to 1.0 */
+  | { type: "Volume"; volume: number };
+
+export interface Safeguarded {
+  type: "Safeguarded";
+  id: string;
+  /** the default output which was unplugged */
+  removed: string;
+  action: SafeguardAction;
+}
+
+export interface VolumeLimited {
+  type: "VolumeLimited";
+  id: string;
+  /** the volume which went above the limit */
+  volume: number;
+  maxVolume: number;
+}
+
+export type Report =
+  | ProfileApplied
+  | AutoSwitched
+  | FallbackSelected
+  | Safeguarded
+  | VolumeLimited;
+
+export type QueryResult =
+  | { type: "Done" }
+  /** after any limit was applied */
+  | { type: "Volume"; id: string; volume: number }
+  | { type: "Muted"; id: string; muted: boolean }
+  | { type: "DefaultChanged"; id: string }
+  | ProfileApplied;
+
+interface APIErrorBase {
+  /** true when the same request may succeed later, e.g. while a device reconnects */
+  retryable: boolean;
+  message: string;
+}
+
+export type APIError = APIErrorBase &
+  (
+    | { code: "UNEXPECTED"; inner: { kind: "LockError" | "MPSCClosedError" } }
+    | { code: "SOMETHING_WRONG"; msg: string }
+    | { code: "DEVICE_NOT_FOUND"; id: string }
+    | { code: "DEVICE_UNAVAILABLE" }
+    | { code: "INVALID_ARGUMENT"; field: string; reason: string }
+    | { code: "AUDIO_SERVICE_UNAVAILABLE" }
+    | { code: "PLATFORM"; hresult: number; context: string }
+    | { code: "SETTINGS_UNREADABLE"; path: string; reason: string }
+  );
+
+export type APIErrorCode = APIError["code"];
 
 export interface AudioStateChangePayload {
   windowsAudioState: WindowsAudioState;
   notification?: Notify;
+  reports: Report[];
 }
 
 
 
+export interface PeakLevels {
+  /** 0.0 to 1.0 */
+  peak: number;
+  channels: number[];
+}
+
+/** payload of the "peak_meter" event */
+export interface MeterPayload {
+  /** keyed by device id, devices which cannot be metered are left out */
+  peaks: Record<string, PeakLevels>;
+}
+
+/** payload of the "backend_status" event */
+export type BackendStatus =
+  /** the backend stopped and is rebuilt after `delayMs` */
+  | { type: "Restarting"; attempt: number; delayMs: number; reason: string }
+  | { type: "Recovered"; attempts: number };
+
+export type HotkeyAction =
+  | { type: "CycleDevice" }
+  | { type: "SwitchDevice"; device: string }
+  | { type: "VolumeStep"; percent: number }
+  | { type: "ToggleMute" }
+  | { type: "ApplyProfile"; name: string };
+
+export interface HotkeyBinding {
+  /** e.g. "Ctrl+Alt+Right" */
+  chord: string;
+  action: HotkeyAction;
+}
+
+export interface DevicePreference {
+  alias?: string | null;
+  hidden?: boolean;
+  order?: number | null;
+  /** 0.0 to 1.0 */
+  maxVolume?: number | null;
+}
+
+export interface Settings {
+  version: number;
+  hotkeys: HotkeyBinding[];
+  /** volume change in percent per wheel notch over the tray icon */
+  trayScrollStep: number;
+  /** peak meter samples per second while the window is shown */
+  meterRate: number;
+  /** keyed by device id */
+  devices: Record<string, DevicePreference>;
+  /** regular expressions matched against device names */
+  hideRules: string[];
+  /** output device ids, most preferred first */
+  priority: string[];
+  autoSwitch: {
+    enabled: boolean;
+    /** roles a newly connected device takes over */
+    roles: Role[];
+  };
+  fallback: {
+    enabled: boolean;
+    /** mute the replacement before it becomes the default */
+    mute: boolean;
+  };
+  safeguard: {
+    enabled: boolean;
+    action: SafeguardAction;
+    /** form factors of the unplugged default which trigger it, empty for any */
+    from: FormFactor[];
+    /** form factors of the new default which are protected, empty for any */
+    to: FormFactor[];
+  };
+}
+
 export interface MeterProps {
   device: AudioDeviceInfo;
   defaultVolume?: number;
@@ -0,0 +1,22 @@
+import { listen } from "@tauri-apps/api/event";
+import { useEffect, useState } from "react";
+import type { BackendStatus } from "./types";
+
+/** The last status reported by the backend supervisor, `null` until something went wrong. */
+const useBackendStatus = () => {
+  const [status, setStatus] = useState<BackendStatus | null>(null);
+
+  useEffect(() => {
+    const unlisten = listen<BackendStatus>("backend_status", (event) => {
+      setStatus(event.payload);
+    });
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  return status;
+}
+
+export default useBackendStatus;
@@ -0,0 +1,54 @@
+import { listen } from "@tauri-apps/api/event";
+import { useEffect, useState } from "react";
+import { describeError, invokeQuery } from "./ipc";
+import type { APIError, BackendStatus, MeterPayload, PeakLevels } from "./types";
+
+const setMetering = (enabled: boolean) => {
+  invokeQuery({ kind: "Metering", enabled }).catch((e: APIError) => {
+    console.error(describeError(e));
+  });
+};
+
+/** Peak levels of `deviceId`, sampled by the backend only while the window is visible. */
+const usePeakMeter = (deviceId: string) => {
+  const [levels, setLevels] = useState<PeakLevels | null>(null);
+
+  useEffect(() => {
+    const unlisten = listen<MeterPayload>("peak_meter", (event) => {
+      setLevels(event.payload.peaks[deviceId] ?? null);
+    });
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [deviceId]);
+
+  useEffect(() => {
+    const handleVisibilityChange = () => {
+      setMetering(!document.hidden);
+      if (document.hidden) {
+        setLevels(null);
+      }
+    };
+
+    handleVisibilityChange();
+    document.addEventListener("visibilitychange", handleVisibilityChange);
+
+    // a rebuilt backend starts with metering off
+    const unlisten = listen<BackendStatus>("backend_status", (event) => {
+      if (event.payload.type === "Recovered") {
+        handleVisibilityChange();
+      }
+    });
+
+    return () => {
+      document.removeEventListener("visibilitychange", handleVisibilityChange);
+      unlisten.then((f) => f());
+      setMetering(false);
+    };
+  }, []);
+
+  return levels;
+}
+
+export default usePeakMeter;